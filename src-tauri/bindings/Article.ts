@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export interface Article { id: string, title: string, url: string, cover: string | null, contents: string, }
\ No newline at end of file
+export interface Article { id: string, title: string, url: string, cover: string | null, contents: string, byline: string | null, siteName: string | null, lang: string | null, dir: string | null, excerpt: string | null, length: number | null, tags: Array<string>, }
\ No newline at end of file
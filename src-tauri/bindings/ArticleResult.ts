@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArticleStatus } from "./ArticleStatus";
+
+export interface ArticleResult { id: string, title: string, url: string, status: ArticleStatus, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FailureReason } from "./FailureReason";
+
+export type ArticleStatus = { status: "sent" } | { status: "placeholder", reason: FailureReason, message: string, } | { status: "failed", reason: FailureReason, message: string, };
\ No newline at end of file
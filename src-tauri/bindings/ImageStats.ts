@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ImageStats { count: number, originalSize: number, optimizedSize: number, dropped: number, }
\ No newline at end of file
@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { Article } from "./Article";
+import type { DeliveryMode } from "./DeliveryMode";
 
-export interface SendInput { articles: Array<Article>, }
\ No newline at end of file
+export interface SendInput { articles: Array<Article>, placeholderForFailures: boolean, mode: DeliveryMode, }
\ No newline at end of file
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArticleResult } from "./ArticleResult";
+import type { ImageStats } from "./ImageStats";
+import type { VolumeResult } from "./VolumeResult";
+
+export interface SendOutput { articles: Array<ArticleResult>, imageStats: ImageStats, volumes: Array<VolumeResult>, warning: string | null, }
\ No newline at end of file
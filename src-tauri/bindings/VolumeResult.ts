@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { VolumeStatus } from "./VolumeStatus";
+
+export interface VolumeResult { title: string, articles: Array<string>, size: number, status: VolumeStatus, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DeliveryFailure } from "./DeliveryFailure";
+
+export type VolumeStatus = { status: "sent" } | { status: "failed", reason: DeliveryFailure, message: string, };
\ No newline at end of file
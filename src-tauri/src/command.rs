@@ -1,5 +1,12 @@
+use crate::domain::service::cache::{CacheService, ClearCacheOutput};
+use crate::domain::service::credential::{
+  CredentialService, ImportCookiesInput, ImportCookiesOutput, ListCredentialsOutput,
+  RemoveCredentialInput, SetHeadersInput,
+};
 use crate::domain::service::pocket::{IsLoginOutput, ListOutput, PocketService, StartLoginOutput};
-use crate::domain::service::send_to_kindle::{SendInput, SendOutput, SendToKindleService};
+use crate::domain::service::send_to_kindle::{
+  SendError, SendInput, SendOutput, SendToKindleService,
+};
 use crate::state::AppState;
 use anyhow::Result;
 use shaku::HasProvider;
@@ -34,8 +41,49 @@ pub async fn list(state: State<'_, AppState>) -> Result<ListOutput, String> {
 }
 
 #[tauri::command]
-pub async fn send(input: SendInput, state: State<'_, AppState>) -> Result<SendOutput, String> {
+pub async fn send(input: SendInput, state: State<'_, AppState>) -> Result<SendOutput, SendError> {
   let mut service: Box<dyn SendToKindleService> = state.module.provide().unwrap();
 
-  service.send(input).await.map_err(|e| e.to_string())
+  service.send(input).await
+}
+
+#[tauri::command]
+pub fn list_credentials(state: State<'_, AppState>) -> Result<ListCredentialsOutput, String> {
+  let mut service: Box<dyn CredentialService> = state.module.provide().unwrap();
+
+  service.list().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn import_cookies(
+  input: ImportCookiesInput,
+  state: State<'_, AppState>,
+) -> Result<ImportCookiesOutput, String> {
+  let mut service: Box<dyn CredentialService> = state.module.provide().unwrap();
+
+  service.import_cookies(input).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_headers(input: SetHeadersInput, state: State<'_, AppState>) -> Result<(), String> {
+  let mut service: Box<dyn CredentialService> = state.module.provide().unwrap();
+
+  service.set_headers(input).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn remove_credential(
+  input: RemoveCredentialInput,
+  state: State<'_, AppState>,
+) -> Result<(), String> {
+  let mut service: Box<dyn CredentialService> = state.module.provide().unwrap();
+
+  service.remove(input).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_cache(state: State<'_, AppState>) -> Result<ClearCacheOutput, String> {
+  let mut service: Box<dyn CacheService> = state.module.provide().unwrap();
+
+  service.clear().await.map_err(|e| e.to_string())
 }
@@ -12,4 +12,37 @@ pub struct Article {
   pub url: String,
   pub cover: Option<String>,
   pub contents: String,
+  #[serde(default)]
+  pub byline: Option<String>,
+  #[serde(default)]
+  pub site_name: Option<String>,
+  /// BCP 47 language tag such as `en` or `ja-JP`
+  #[serde(default)]
+  pub lang: Option<String>,
+  /// `ltr`, `rtl` or `auto`
+  #[serde(default)]
+  pub dir: Option<String>,
+  #[serde(default)]
+  pub excerpt: Option<String>,
+  /// number of characters of the text
+  #[serde(default)]
+  pub length: Option<usize>,
+  /// Pocket tags, sorted
+  #[serde(default)]
+  pub tags: Vec<String>,
+}
+
+impl Article {
+  /// Estimated minutes to read the article from its length in characters, at
+  /// about 1000 characters per minute, or 500 for Chinese, Japanese and Korean.
+  pub fn reading_minutes(&self) -> Option<usize> {
+    let length = self.length.filter(|length| *length > 0)?;
+    // Chinese, Japanese and Korean have no spaces and more information per
+    // character
+    let per_minute = match self.lang.as_deref().map(|lang| lang.split('-').next()) {
+      Some(Some("ja" | "zh" | "ko")) => 500,
+      _ => 1000,
+    };
+    Some(length.div_ceil(per_minute))
+  }
 }
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// How the articles are split into books.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryMode {
+  /// all articles in one book
+  #[default]
+  Digest,
+  /// a book for each article, with its own title and author
+  PerArticle,
+  /// a book for each tag, by the first tag of each article
+  PerTag,
+}
@@ -0,0 +1,23 @@
+use serde::Serialize;
+use ts_rs::TS;
+
+/// An image embedded into the book.
+#[derive(Debug, Clone)]
+pub struct Image {
+  /// path inside the book, referenced by `src` in the articles
+  pub path: String,
+  pub mime: String,
+  pub data: Vec<u8>,
+}
+
+/// Size of the images before and after they were prepared for the device.
+#[derive(Serialize, Debug, Default, Clone, Copy, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageStats {
+  pub count: usize,
+  pub original_size: usize,
+  pub optimized_size: usize,
+  /// images removed from the book as they could not be decoded, e.g. AVIF
+  pub dropped: usize,
+}
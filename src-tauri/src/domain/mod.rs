@@ -1,5 +1,9 @@
 mod article;
+mod delivery;
+mod image;
 pub mod repository;
 pub mod service;
 
 pub use article::*;
+pub use delivery::*;
+pub use image::*;
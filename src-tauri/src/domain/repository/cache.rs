@@ -0,0 +1,56 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use shaku::Interface;
+
+#[derive(Debug, Clone, Copy)]
+pub enum CacheKind {
+  /// raw HTML of article pages
+  Page,
+  /// `Readability.js` output
+  Extracted,
+  Image,
+}
+
+impl CacheKind {
+  pub const ALL: [CacheKind; 3] = [CacheKind::Page, CacheKind::Extracted, CacheKind::Image];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      CacheKind::Page => "pages",
+      CacheKind::Extracted => "extracted",
+      CacheKind::Image => "images",
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheEntry {
+  /// the URL after redirects
+  pub url: String,
+  pub content_type: Option<String>,
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+  /// seconds since the epoch, set when stored
+  #[serde(default)]
+  pub fetched_at: u64,
+  /// recent enough to be used without revalidation
+  #[serde(skip)]
+  pub fresh: bool,
+  #[serde(skip)]
+  pub data: Vec<u8>,
+}
+
+pub struct ClearOutput {
+  pub entries: usize,
+  pub size: u64,
+}
+
+/// Responses stored on disk, keyed by normalized URL.
+#[async_trait]
+pub trait CacheRepository: Interface {
+  async fn get(&self, kind: CacheKind, url: &str) -> Option<CacheEntry>;
+  async fn put(&self, kind: CacheKind, url: &str, entry: &CacheEntry) -> Result<()>;
+  async fn clear(&self) -> Result<ClearOutput>;
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use shaku::Interface;
+use url::Url;
+
+use crate::library::cookies::Cookie;
+
+/// What is sent to the sites matching `domain` and its subdomains.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Credential {
+  pub domain: String,
+  #[serde(default)]
+  pub headers: BTreeMap<String, String>,
+  #[serde(default)]
+  pub cookies: Vec<Cookie>,
+}
+
+pub struct ImportCookiesInput {
+  /// contents of a cookies.txt file
+  pub contents: String,
+}
+
+pub struct ImportCookiesOutput {
+  pub domains: Vec<String>,
+  pub cookies: usize,
+}
+
+pub struct SetHeadersInput {
+  pub domain: String,
+  /// replaces the headers of the domain; empty removes them
+  pub headers: BTreeMap<String, String>,
+}
+
+pub struct RemoveInput {
+  pub domain: String,
+}
+
+pub trait CredentialRepository: Interface {
+  fn list(&self) -> Vec<Credential>;
+  fn import_cookies(&self, input: ImportCookiesInput) -> Result<ImportCookiesOutput>;
+  fn set_headers(&self, input: SetHeadersInput) -> Result<()>;
+  fn remove(&self, input: RemoveInput) -> Result<()>;
+  /// Headers to authenticate a request to `url`, including `Cookie`.
+  fn headers(&self, url: &Url) -> Vec<(String, String)>;
+}
@@ -0,0 +1,17 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use shaku::Interface;
+
+pub struct FetchInput {
+  pub url: String,
+}
+
+pub struct FetchOutput {
+  pub mime: String,
+  pub data: Vec<u8>,
+}
+
+#[async_trait]
+pub trait ImageRepository: Interface {
+  async fn fetch(&self, input: FetchInput) -> Result<FetchOutput>;
+}
@@ -1,14 +1,53 @@
-use anyhow::Result;
+use std::collections::HashMap;
+
 use async_trait::async_trait;
 use shaku::Interface;
+use thiserror::Error;
 
-use crate::domain::Article;
+use crate::domain::{Article, ArticleId, DeliveryMode, Image, ImageStats};
 
 pub struct SendInput {
   pub articles: Vec<Article>,
+  pub images: Vec<Image>,
+  /// path in `images` of the Pocket cover of each article
+  pub covers: HashMap<ArticleId, String>,
+  pub mode: DeliveryMode,
+}
+
+pub struct SendOutput {
+  pub image_stats: ImageStats,
+  pub volumes: Vec<VolumeOutput>,
+}
+
+/// A book sent on its own, as large books are split into volumes.
+pub struct VolumeOutput {
+  pub title: String,
+  pub ids: Vec<ArticleId>,
+  /// bytes of the EPUB
+  pub size: usize,
+  pub result: Result<(), DeliveryError>,
+}
+
+#[derive(Error, Debug)]
+pub enum DeliveryError {
+  /// a failed login or a sender refused by policy, such as one missing from
+  /// the approved senders of Send to Kindle
+  #[error("the SMTP server rejected the login or the sender: {0}")]
+  Auth(anyhow::Error),
+  #[error("the recipient was rejected: {0}")]
+  RejectedRecipient(anyhow::Error),
+  #[error("the message is too large: {0}")]
+  TooLarge(anyhow::Error),
+  /// network errors and 4xx replies, which may succeed if retried
+  #[error("temporary delivery failure: {0}")]
+  Transient(anyhow::Error),
+  #[error("invalid email address: {0}")]
+  InvalidAddress(anyhow::Error),
+  #[error("{0:#}")]
+  Other(#[from] anyhow::Error),
 }
 
 #[async_trait]
 pub trait KindleRepository: Interface {
-  async fn send(&self, input: SendInput) -> Result<()>;
+  async fn send(&self, input: SendInput) -> Result<SendOutput, DeliveryError>;
 }
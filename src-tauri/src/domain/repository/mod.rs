@@ -1,3 +1,6 @@
+pub mod cache;
+pub mod credential;
+pub mod image;
 pub mod kindle;
 pub mod pocket;
 pub mod readability;
@@ -1,6 +1,6 @@
-use anyhow::Result;
 use async_trait::async_trait;
 use shaku::Interface;
+use thiserror::Error;
 
 use crate::domain::Article;
 
@@ -12,7 +12,17 @@ pub struct ConvertOutput {
   pub article: Article,
 }
 
+#[derive(Error, Debug)]
+pub enum ConvertError {
+  #[error("failed to fetch the article: {0:#}")]
+  Fetch(anyhow::Error),
+  #[error("failed to extract the article: {0:#}")]
+  Extract(anyhow::Error),
+  #[error("{0:#}")]
+  Timeout(anyhow::Error),
+}
+
 #[async_trait]
 pub trait ReadabilityRepository: Interface {
-  async fn convert(&self, input: ConvertInput) -> Result<ConvertOutput>;
+  async fn convert(&self, input: ConvertInput) -> Result<ConvertOutput, ConvertError>;
 }
@@ -0,0 +1,19 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use ts_rs::TS;
+
+#[async_trait]
+pub trait CacheService: Send {
+  async fn clear(&mut self) -> Result<ClearCacheOutput>;
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearCacheOutput {
+  pub entries: usize,
+  /// bytes freed, well below `Number.MAX_SAFE_INTEGER`
+  #[ts(type = "number")]
+  pub size: u64,
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Manage the credentials of subscribed sites. Secrets are never sent back to
+/// the UI, only what is stored for each domain.
+pub trait CredentialService: Send {
+  fn list(&mut self) -> Result<ListCredentialsOutput>;
+  fn import_cookies(&mut self, input: ImportCookiesInput) -> Result<ImportCookiesOutput>;
+  fn set_headers(&mut self, input: SetHeadersInput) -> Result<()>;
+  fn remove(&mut self, input: RemoveCredentialInput) -> Result<()>;
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialSummary {
+  pub domain: String,
+  pub header_names: Vec<String>,
+  pub cookies: usize,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ListCredentialsOutput {
+  pub credentials: Vec<CredentialSummary>,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCookiesInput {
+  /// contents of a cookies.txt file
+  pub contents: String,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCookiesOutput {
+  pub domains: Vec<String>,
+  pub cookies: usize,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct SetHeadersInput {
+  pub domain: String,
+  pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoveCredentialInput {
+  pub domain: String,
+}
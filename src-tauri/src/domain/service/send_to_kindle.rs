@@ -1,13 +1,14 @@
 use async_trait::async_trait;
 
-use crate::domain::Article;
-use anyhow::Result;
+use crate::domain::{
+  repository::kindle::DeliveryError, Article, ArticleId, DeliveryMode, ImageStats,
+};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
 #[async_trait]
 pub trait SendToKindleService: Send {
-  async fn send(&mut self, input: SendInput) -> Result<SendOutput>;
+  async fn send(&mut self, input: SendInput) -> Result<SendOutput, SendError>;
 }
 
 #[derive(Serialize, Deserialize, TS)]
@@ -15,10 +16,140 @@ pub trait SendToKindleService: Send {
 #[serde(rename_all = "camelCase")]
 pub struct SendInput {
   pub articles: Vec<Article>,
+  /// add a chapter with the Pocket excerpt and the original URL for articles
+  /// that could not be converted
+  #[serde(default)]
+  pub placeholder_for_failures: bool,
+  #[serde(default)]
+  pub mode: DeliveryMode,
 }
 
-// #[derive(Serialize, TS)]
-#[derive(Serialize)]
-// #[ts(export)]
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct SendOutput {
+  pub articles: Vec<ArticleResult>,
+  pub image_stats: ImageStats,
+  /// books sent, more than one if the digest exceeded the Send to Kindle limits
+  pub volumes: Vec<VolumeResult>,
+  /// the books were sent, but something went wrong afterwards, e.g. the
+  /// articles could not be marked as sent in Pocket
+  pub warning: Option<String>,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
 #[serde(rename_all = "camelCase")]
-pub struct SendOutput {}
+pub struct VolumeResult {
+  pub title: String,
+  pub articles: Vec<ArticleId>,
+  /// bytes of the EPUB
+  pub size: usize,
+  pub status: VolumeStatus,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum VolumeStatus {
+  Sent,
+  Failed {
+    reason: DeliveryFailure,
+    message: String,
+  },
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct ArticleResult {
+  pub id: ArticleId,
+  pub title: String,
+  pub url: String,
+  pub status: ArticleStatus,
+}
+
+#[derive(Serialize, TS)]
+#[ts(export)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum ArticleStatus {
+  Sent,
+  /// delivered as a placeholder chapter
+  Placeholder {
+    reason: FailureReason,
+    message: String,
+  },
+  Failed {
+    reason: FailureReason,
+    message: String,
+  },
+}
+
+#[derive(Serialize, TS, Clone, Copy)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureReason {
+  Fetch,
+  Extract,
+  Timeout,
+  /// the volume containing the article could not be sent
+  Delivery,
+}
+
+/// Why sending failed as a whole, i.e. no volume was delivered. No article is
+/// marked as sent then.
+#[derive(Serialize, TS, Debug)]
+#[ts(export)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SendError {
+  Delivery {
+    reason: DeliveryFailure,
+    message: String,
+  },
+  Other {
+    message: String,
+  },
+}
+
+#[derive(Serialize, TS, Debug, Clone, Copy)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryFailure {
+  Auth,
+  RejectedRecipient,
+  TooLarge,
+  /// still failing after retries
+  Transient,
+  InvalidAddress,
+  Other,
+}
+
+impl From<&DeliveryError> for DeliveryFailure {
+  fn from(e: &DeliveryError) -> Self {
+    match e {
+      DeliveryError::Auth(_) => DeliveryFailure::Auth,
+      DeliveryError::RejectedRecipient(_) => DeliveryFailure::RejectedRecipient,
+      DeliveryError::TooLarge(_) => DeliveryFailure::TooLarge,
+      DeliveryError::Transient(_) => DeliveryFailure::Transient,
+      DeliveryError::InvalidAddress(_) => DeliveryFailure::InvalidAddress,
+      DeliveryError::Other(_) => DeliveryFailure::Other,
+    }
+  }
+}
+
+impl From<DeliveryError> for SendError {
+  fn from(e: DeliveryError) -> Self {
+    SendError::Delivery {
+      reason: (&e).into(),
+      message: e.to_string(),
+    }
+  }
+}
+
+impl From<anyhow::Error> for SendError {
+  fn from(e: anyhow::Error) -> Self {
+    SendError::Other {
+      message: format!("{:#}", e),
+    }
+  }
+}
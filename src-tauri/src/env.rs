@@ -1,9 +1,69 @@
 use serde::Deserialize;
 
+use crate::repository::kindle::{Sections, SmtpAuth, SmtpTls};
+
 #[derive(Deserialize, Debug)]
 pub struct Env {
   pub pocket_platform_consumer_key: String,
   pub send_to_kindle_email: String,
   pub email_user: String,
   pub email_password: String,
+  pub fetch_timeout_secs: Option<u64>,
+  pub fetch_concurrency: Option<usize>,
+  pub fetch_concurrency_per_domain: Option<usize>,
+  pub readability_timeout_secs: Option<u64>,
+  pub readability_workers: Option<usize>,
+  pub readability_render_javascript: Option<bool>,
+  pub readability_max_pages: Option<usize>,
+  pub image_max_width: Option<u32>,
+  pub image_max_height: Option<u32>,
+  pub image_grayscale: Option<bool>,
+  pub image_dither: Option<bool>,
+  pub image_jpeg_quality: Option<u8>,
+  pub image_fetch_timeout_secs: Option<u64>,
+  /// images larger than this are skipped
+  pub image_max_size_mb: Option<usize>,
+  pub image_fetch_concurrency: Option<usize>,
+  pub http_user_agent: Option<String>,
+  pub http_connect_timeout_secs: Option<u64>,
+  pub http_timeout_secs: Option<u64>,
+  /// e.g. `http://proxy:8080` or `socks5://localhost:1080`
+  pub http_proxy: Option<String>,
+  pub http_pool_max_idle_per_host: Option<usize>,
+  pub http_max_redirects: Option<usize>,
+  /// e.g. `smtp.fastmail.com`, or `localhost` for a test sink
+  pub smtp_host: Option<String>,
+  pub smtp_port: Option<u16>,
+  /// `implicit`, `starttls` or `none`
+  pub smtp_tls: Option<SmtpTls>,
+  /// `plain`, `login`, `xoauth2` or `none`
+  pub smtp_auth: Option<SmtpAuth>,
+  /// the sender address if not set
+  pub smtp_username: Option<String>,
+  pub smtp_timeout_secs: Option<u64>,
+  pub smtp_retries: Option<usize>,
+  /// the Send to Kindle limit is 50 MB per email
+  pub kindle_max_message_size_mb: Option<usize>,
+  pub kindle_max_attachments: Option<usize>,
+  /// e.g. `Reading list – {date}`
+  pub book_title: Option<String>,
+  /// e.g. `Reading list – {date} – {tag}`
+  pub book_tag_title: Option<String>,
+  pub book_author: Option<String>,
+  pub book_publisher: Option<String>,
+  /// `none`, `tag`, `site` or `source`
+  pub book_sections: Option<Sections>,
+  /// base font size in `em`
+  pub book_font_size: Option<f32>,
+  pub book_line_height: Option<f32>,
+  /// embed only the glyphs used by each book
+  pub book_subset_fonts: Option<bool>,
+  /// write Japanese books vertically
+  pub book_vertical_japanese: Option<bool>,
+  pub cover_enabled: Option<bool>,
+  /// show the Pocket cover of the first article on the cover
+  pub cover_lead_image: Option<bool>,
+  pub cache_max_size_mb: Option<u64>,
+  /// how long cached pages and images are used without revalidation
+  pub cache_fresh_for_secs: Option<u64>,
 }
@@ -0,0 +1,122 @@
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+
+/// how far into the document to look for `<meta charset>`
+const META_PRESCAN_LENGTH: usize = 4096;
+
+/// Decode an HTML page. The encoding is taken from the byte order mark, the
+/// `Content-Type` header or `<meta>` in this order, and guessed from the
+/// content if none of them declares it.
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>, tld: Option<&str>) -> String {
+  let encoding = Encoding::for_bom(bytes)
+    .map(|(encoding, _)| encoding)
+    .or_else(|| content_type.and_then(charset_of_content_type))
+    .or_else(|| charset_of_meta(bytes))
+    .unwrap_or_else(|| {
+      let mut detector = EncodingDetector::new();
+      detector.feed(bytes, true);
+      detector.guess(tld.map(str::as_bytes), true)
+    });
+
+  log::debug!("decode as {}", encoding.name());
+
+  // removes the BOM as well
+  let (text, _, malformed) = encoding.decode(bytes);
+  if malformed {
+    log::warn!("malformed {} sequences replaced", encoding.name());
+  }
+  text.into_owned()
+}
+
+fn charset_of_content_type(content_type: &str) -> Option<&'static Encoding> {
+  content_type
+    .split(';')
+    .filter_map(|param| param.split_once('='))
+    .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+    .and_then(|(_, value)| label(value))
+}
+
+/// Find `<meta charset="...">` or `<meta http-equiv="Content-Type" content="...; charset=...">`.
+fn charset_of_meta(bytes: &[u8]) -> Option<&'static Encoding> {
+  let head = &bytes[..bytes.len().min(META_PRESCAN_LENGTH)];
+  // the charset declaration itself is ASCII in every encoding we care about
+  let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+
+  head
+    .match_indices("<meta")
+    .filter_map(|(start, _)| {
+      let tag = &head[start..];
+      let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+      let (_, value) = tag.split_once("charset")?;
+      let value = value.trim_start().strip_prefix('=')?;
+      label(value)
+    })
+    .next()
+    // a page can't be decoded as UTF-16 after sniffing ASCII, see the HTML spec
+    .map(|encoding| encoding.output_encoding())
+}
+
+fn label(value: &str) -> Option<&'static Encoding> {
+  let value = value.trim_start().trim_start_matches(['"', '\'']);
+  let end = value
+    .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | ';' | '/' | '>'))
+    .unwrap_or(value.len());
+  Encoding::for_label(&value.as_bytes()[..end])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// `日本` in Shift_JIS
+  const SHIFT_JIS: &[u8] = &[0x93, 0xfa, 0x96, 0x7b];
+
+  #[test]
+  fn decode_html_uses_the_content_type() {
+    assert_eq!(
+      decode_html(SHIFT_JIS, Some("text/html; charset=Shift_JIS"), None),
+      "日本"
+    );
+    assert_eq!(
+      decode_html(SHIFT_JIS, Some("text/html; charset=\"shift_jis\""), None),
+      "日本"
+    );
+  }
+
+  #[test]
+  fn decode_html_prefers_the_bom() {
+    let bytes = [&[0xef, 0xbb, 0xbf][..], "é".as_bytes()].concat();
+    assert_eq!(
+      decode_html(&bytes, Some("text/html; charset=iso-8859-1"), None),
+      "é"
+    );
+  }
+
+  #[test]
+  fn decode_html_reads_meta() {
+    let bytes = [&b"<meta charset=\"shift_jis\"><p>"[..], SHIFT_JIS].concat();
+    assert!(decode_html(&bytes, Some("text/html"), None).ends_with("<p>日本"));
+
+    let bytes = [
+      &b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=Shift_JIS\">"[..],
+      SHIFT_JIS,
+    ]
+    .concat();
+    assert!(decode_html(&bytes, None, None).ends_with("日本"));
+  }
+
+  #[test]
+  fn decode_html_ignores_utf16_in_meta() {
+    let html = "<meta charset=\"utf-16\"><p>é";
+    assert_eq!(decode_html(html.as_bytes(), None, None), html);
+  }
+
+  #[test]
+  fn decode_html_guesses_without_declaration() {
+    let bytes = "<p>これは日本語の文章です。</p>".as_bytes();
+    assert_eq!(
+      decode_html(bytes, None, Some("jp")),
+      "<p>これは日本語の文章です。</p>"
+    );
+  }
+}
@@ -0,0 +1,183 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// prefix the browsers give to `HttpOnly` cookies in cookies.txt
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Cookie {
+  /// host without the leading dot
+  pub domain: String,
+  pub include_subdomains: bool,
+  pub path: String,
+  pub secure: bool,
+  /// seconds since the epoch, `None` for session cookies
+  pub expires: Option<u64>,
+  pub name: String,
+  pub value: String,
+}
+
+impl Cookie {
+  pub fn matches(&self, url: &Url, now: u64) -> bool {
+    let host = match url.host_str() {
+      Some(host) => host,
+      None => return false,
+    };
+
+    let domain = host == self.domain
+      || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+    let path = url.path().starts_with(&self.path)
+      && (self.path.ends_with('/')
+        || url.path().len() == self.path.len()
+        || url.path()[self.path.len()..].starts_with('/'));
+    let secure = !self.secure || url.scheme() == "https";
+    let alive = self.expires.is_none_or(|expires| expires > now);
+
+    domain && path && secure && alive
+  }
+
+  /// Whether both are the same cookie, regardless of the value.
+  pub fn same(&self, other: &Cookie) -> bool {
+    self.domain == other.domain && self.path == other.path && self.name == other.name
+  }
+}
+
+/// Parse the Netscape cookies.txt format exported by browser extensions and
+/// `curl -c`.
+///
+/// Each line is `domain`, `include subdomains`, `path`, `secure`, `expires`,
+/// `name` and `value` separated by tabs.
+pub fn parse_netscape(text: &str) -> Result<Vec<Cookie>> {
+  let mut cookies = vec![];
+
+  for (i, line) in text.lines().enumerate() {
+    let line = line.trim_end_matches('\r');
+    let line = line.strip_prefix(HTTP_ONLY_PREFIX).unwrap_or(line);
+    if line.trim().is_empty() || line.starts_with('#') {
+      continue;
+    }
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() < 7 {
+      bail!("line {}: expected 7 tab-separated fields", i + 1);
+    }
+
+    let expires = match fields[4].trim().parse::<u64>() {
+      Ok(0) => None,
+      Ok(expires) => Some(expires),
+      Err(_) => bail!("line {}: invalid expiration {:?}", i + 1, fields[4]),
+    };
+
+    cookies.push(Cookie {
+      domain: fields[0].trim().trim_start_matches('.').to_lowercase(),
+      include_subdomains: fields[1].eq_ignore_ascii_case("TRUE") || fields[0].starts_with('.'),
+      path: match fields[2] {
+        "" => "/".into(),
+        path => path.into(),
+      },
+      secure: fields[3].eq_ignore_ascii_case("TRUE"),
+      expires,
+      name: fields[5].into(),
+      value: fields[6].into(),
+    });
+  }
+
+  Ok(cookies)
+}
+
+/// The `Cookie` header value for `url`, or `None` if no cookie applies.
+pub fn cookie_header<'a>(
+  cookies: impl IntoIterator<Item = &'a Cookie>,
+  url: &Url,
+) -> Option<String> {
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_secs());
+
+  let pairs: Vec<String> = cookies
+    .into_iter()
+    .filter(|cookie| cookie.matches(url, now))
+    .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+    .collect();
+
+  if pairs.is_empty() {
+    None
+  } else {
+    Some(pairs.join("; "))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const COOKIES: &str = "# Netscape HTTP Cookie File\r
+.example.com\tTRUE\t/\tTRUE\t0\tsession\tabc\r
+#HttpOnly_news.example.org\tFALSE\t/paid\tFALSE\t4102444800\ttoken\tx=y\r
+\r
+";
+
+  #[test]
+  fn parse_netscape_reads_every_field() {
+    let cookies = parse_netscape(COOKIES).unwrap();
+    assert_eq!(
+      cookies,
+      vec![
+        Cookie {
+          domain: "example.com".into(),
+          include_subdomains: true,
+          path: "/".into(),
+          secure: true,
+          expires: None,
+          name: "session".into(),
+          value: "abc".into(),
+        },
+        Cookie {
+          domain: "news.example.org".into(),
+          include_subdomains: false,
+          path: "/paid".into(),
+          secure: false,
+          expires: Some(4102444800),
+          name: "token".into(),
+          value: "x=y".into(),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn parse_netscape_keeps_empty_values() {
+    let cookies = parse_netscape("example.com\tFALSE\t/\tFALSE\t0\tname\t").unwrap();
+    assert_eq!(cookies[0].value, "");
+  }
+
+  #[test]
+  fn parse_netscape_rejects_missing_fields() {
+    let e = parse_netscape("example.com\tFALSE\t/\tFALSE\t0\tname").unwrap_err();
+    assert_eq!(e.to_string(), "line 1: expected 7 tab-separated fields");
+    assert!(parse_netscape("example.com\tFALSE\t/\tFALSE\tnever\tname\tvalue").is_err());
+  }
+
+  #[test]
+  fn cookie_header_matches_domain_path_and_scheme() {
+    let cookies = parse_netscape(COOKIES).unwrap();
+    let header = |url: &str| cookie_header(&cookies, &Url::parse(url).unwrap());
+
+    assert_eq!(
+      header("https://www.example.com/a").as_deref(),
+      Some("session=abc")
+    );
+    // secure only
+    assert_eq!(header("http://example.com/"), None);
+    assert_eq!(
+      header("http://news.example.org/paid/1").as_deref(),
+      Some("token=x=y")
+    );
+    assert_eq!(header("http://news.example.org/paidwall"), None);
+    assert_eq!(header("http://sub.news.example.org/paid"), None);
+  }
+}
@@ -0,0 +1,571 @@
+use std::{collections::HashSet, ops::Range};
+
+use kuchikiki::{
+  traits::{NodeIterator, TendrilSink},
+  ElementData, NodeData, NodeRef,
+};
+use url::Url;
+
+use super::url::strip_tracking;
+
+pub const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+
+const VOID_ELEMENTS: &[&str] = &[
+  "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+  "wbr",
+];
+
+/// Escape text for use in HTML or XHTML content and attribute values.
+/// Characters which are not allowed in XML are dropped.
+pub fn escape(text: &str) -> String {
+  let mut ret = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '&' => ret.push_str("&amp;"),
+      '<' => ret.push_str("&lt;"),
+      '>' => ret.push_str("&gt;"),
+      '"' => ret.push_str("&quot;"),
+      '\'' => ret.push_str("&#39;"),
+      '\t' | '\n' | '\r' => ret.push(c),
+      '\u{0}'..='\u{1f}' | '\u{fffe}' | '\u{ffff}' => {}
+      _ => ret.push(c),
+    }
+  }
+  ret
+}
+
+/// Parse an HTML fragment such as the output of `Readability.js` into a document.
+pub fn parse(html: &str) -> NodeRef {
+  kuchikiki::parse_html().one(html)
+}
+
+/// Serialize the contents of `<body>` of a document returned by `parse` as
+/// XHTML, which can be parsed again as HTML.
+pub fn inner_html(document: &NodeRef) -> String {
+  let mut ret = String::new();
+  if let Ok(body) = document.select_first("body") {
+    for child in body.as_node().children() {
+      serialize(&child, &mut ret);
+    }
+  }
+  ret
+}
+
+fn serialize(node: &NodeRef, out: &mut String) {
+  match node.data() {
+    NodeData::Element(element) => {
+      let name = &*element.name.local;
+      if !is_xml_name(name) {
+        node.children().for_each(|c| serialize(&c, out));
+        return;
+      }
+
+      out.push('<');
+      out.push_str(name);
+      for (attribute, value) in element.attributes.borrow().map.iter() {
+        if is_xml_name(&attribute.local) {
+          out.push(' ');
+          out.push_str(&attribute.local);
+          out.push_str("=\"");
+          out.push_str(&escape(&value.value));
+          out.push('"');
+        }
+      }
+
+      if VOID_ELEMENTS.contains(&name) {
+        out.push_str(" />");
+        return;
+      }
+
+      out.push('>');
+      node.children().for_each(|c| serialize(&c, out));
+      out.push_str("</");
+      out.push_str(name);
+      out.push('>');
+    }
+    NodeData::Text(text) => out.push_str(&escape(&text.borrow())),
+    NodeData::Document(_) | NodeData::DocumentFragment => {
+      node.children().for_each(|c| serialize(&c, out));
+    }
+    NodeData::Comment(_) | NodeData::ProcessingInstruction(_) | NodeData::Doctype(_) => {}
+  }
+}
+
+fn is_xml_name(name: &str) -> bool {
+  let mut chars = name.chars();
+  match chars.next() {
+    Some(c) if c.is_alphabetic() || c == '_' => {
+      chars.all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    }
+    _ => false,
+  }
+}
+
+fn is_simple_id(id: &str) -> bool {
+  id.starts_with(|c: char| c.is_ascii_alphabetic())
+    && id
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub fn is_element(node: &NodeRef, name: &str) -> bool {
+  node
+    .as_element()
+    .map(|e| &*e.name.local == name)
+    .unwrap_or(false)
+}
+
+/// attributes used by lazy loading scripts, preferred over `src`
+const LAZY_SRC_ATTRIBUTES: &[&str] = &["data-src", "data-original", "data-lazy-src"];
+const LAZY_SRCSET_ATTRIBUTES: &[&str] = &["data-srcset", "data-lazy-srcset"];
+
+/// Source of every `<img>` worth downloading, in document order.
+pub fn image_sources(html: &str) -> Vec<String> {
+  let document = parse(html);
+  let mut ret = vec![];
+  if let Ok(images) = document.select("img") {
+    for img in images {
+      if let Some(src) = image_source(img.as_node(), &img) {
+        ret.push(src);
+      }
+    }
+  }
+  ret
+}
+
+/// Point every `<img>` at the `src` returned by `f`, which receives the source
+/// from `image_sources`. Images for which `f` returns `None` are removed.
+pub fn rewrite_images<F: FnMut(&str) -> Option<String>>(html: &str, mut f: F) -> String {
+  let document = parse(html);
+
+  // collect first as the tree is modified while iterating
+  let images: Vec<_> = match document.select("img") {
+    Ok(images) => images.collect(),
+    Err(_) => return html.into(),
+  };
+
+  for img in images {
+    let node = img.as_node();
+    let src = image_source(node, &img).and_then(|src| f(&src));
+
+    // alternative sources of a `<picture>` would bypass the new `src`
+    if let Some(picture) = node.parent().filter(|p| is_element(p, "picture")) {
+      let sources: Vec<_> = picture
+        .children()
+        .filter(|c| is_element(c, "source"))
+        .collect();
+      for source in sources {
+        source.detach();
+      }
+    }
+
+    match src {
+      Some(src) => {
+        let mut attributes = img.attributes.borrow_mut();
+        for name in LAZY_SRC_ATTRIBUTES
+          .iter()
+          .chain(LAZY_SRCSET_ATTRIBUTES)
+          .chain(&["srcset", "sizes", "loading"])
+        {
+          attributes.remove(*name);
+        }
+        attributes.insert("src", src);
+      }
+      None => node.detach(),
+    }
+  }
+
+  inner_html(&document)
+}
+
+/// A heading of an HTML fragment, linked from the table of contents.
+pub struct Heading {
+  /// 2 for `<h2>`, 3 for `<h3>`
+  pub level: i32,
+  pub id: String,
+  pub title: String,
+}
+
+/// List the `<h2>` and `<h3>` headings of `html` in document order, giving an
+/// ID to those without a usable one. Generated IDs are numbered in document order, so
+/// they are the same every time the fragment is converted. Headings without
+/// text are skipped.
+pub fn outline(html: &str) -> (String, Vec<Heading>) {
+  let document = parse(html);
+  let elements: Vec<_> = match document.select("h2, h3") {
+    Ok(elements) => elements.collect(),
+    Err(_) => return (html.into(), vec![]),
+  };
+  if elements.is_empty() {
+    return (html.into(), vec![]);
+  }
+
+  let mut ids: HashSet<String> = match document.select("[id]") {
+    Ok(nodes) => nodes
+      .filter_map(|node| node.attributes.borrow().get("id").map(String::from))
+      .collect(),
+    Err(_) => HashSet::new(),
+  };
+
+  let mut headings = vec![];
+  let mut n = 0;
+  for element in elements {
+    let title = element
+      .text_contents()
+      .split_whitespace()
+      .collect::<Vec<_>>()
+      .join(" ");
+    if title.is_empty() {
+      continue;
+    }
+
+    let mut attributes = element.attributes.borrow_mut();
+    // existing IDs are kept if they can be used in a link as is
+    let id = match attributes.get("id").filter(|id| is_simple_id(id)) {
+      Some(id) => id.to_string(),
+      None => {
+        let id = loop {
+          n += 1;
+          let id = format!("heading-{}", n);
+          if !ids.contains(&id) {
+            break id;
+          }
+        };
+        ids.insert(id.clone());
+        attributes.insert("id", id.clone());
+        id
+      }
+    };
+
+    headings.push(Heading {
+      level: if &*element.name.local == "h2" { 2 } else { 3 },
+      id,
+      title,
+    });
+  }
+
+  (inner_html(&document), headings)
+}
+
+/// elements whose text keeps its orientation in vertical writing
+const NOT_UPRIGHT_ELEMENTS: &[&str] = &["pre", "code", "kbd", "samp", "rt", "rp"];
+
+/// Wrap runs of one or two half-width digits, and pairs of `!` and `?`, in
+/// `<span class="tcy">` so they are set upright in vertical writing
+/// (tate-chu-yoko). Longer numbers are left rotated, as they would not fit in
+/// a character.
+pub fn combine_upright(html: &str) -> String {
+  let document = parse(html);
+  let texts: Vec<_> = document.descendants().text_nodes().collect();
+
+  for text in texts {
+    let node = text.as_node();
+    if node
+      .ancestors()
+      .any(|a| NOT_UPRIGHT_ELEMENTS.iter().any(|name| is_element(&a, name)))
+    {
+      continue;
+    }
+
+    let value = text.borrow().clone();
+    let runs = upright_runs(&value);
+    if runs.is_empty() {
+      continue;
+    }
+
+    let mut last = 0;
+    for run in runs {
+      if run.start > last {
+        node.insert_before(NodeRef::new_text(&value[last..run.start]));
+      }
+      // kuchikiki cannot name a new element without html5ever, so the span
+      // is parsed
+      let fragment = parse(&format!(
+        "<span class=\"tcy\">{}</span>",
+        escape(&value[run.clone()])
+      ));
+      if let Ok(span) = fragment.select_first("span") {
+        let span = span.as_node();
+        span.detach();
+        node.insert_before(span.clone());
+      }
+      last = run.end;
+    }
+    if last < value.len() {
+      node.insert_before(NodeRef::new_text(&value[last..]));
+    }
+    node.detach();
+  }
+
+  inner_html(&document)
+}
+
+/// Byte ranges of `text` to set upright.
+fn upright_runs(text: &str) -> Vec<Range<usize>> {
+  let chars: Vec<(usize, char)> = text.char_indices().collect();
+  // part of a word, a decimal or a grouped number such as `A4` or `1,000`
+  let joined = |c: Option<&(usize, char)>| {
+    c.is_some_and(|(_, c)| c.is_ascii_alphanumeric() || matches!(c, '.' | ','))
+  };
+
+  let mut ret = vec![];
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i].1;
+    let digit = c.is_ascii_digit();
+    if !digit && !matches!(c, '!' | '?') {
+      i += 1;
+      continue;
+    }
+
+    let mut end = i + 1;
+    while end < chars.len()
+      && if digit {
+        chars[end].1.is_ascii_digit()
+      } else {
+        matches!(chars[end].1, '!' | '?')
+      }
+    {
+      end += 1;
+    }
+
+    let upright = if digit {
+      end - i <= 2 && !joined(i.checked_sub(1).map(|p| &chars[p])) && !joined(chars.get(end))
+    } else {
+      end - i == 2
+    };
+    if upright {
+      let stop = chars.get(end).map_or(text.len(), |(index, _)| *index);
+      ret.push(chars[i].0..stop);
+    }
+    i = end;
+  }
+  ret
+}
+
+fn image_source(node: &NodeRef, img: &ElementData) -> Option<String> {
+  let attributes = img.attributes.borrow();
+
+  let lazy = LAZY_SRC_ATTRIBUTES
+    .iter()
+    .filter_map(|name| attributes.get(*name))
+    .map(String::from)
+    .chain(
+      LAZY_SRCSET_ATTRIBUTES
+        .iter()
+        .filter_map(|name| attributes.get(*name))
+        .filter_map(largest_candidate),
+    );
+  let srcset = attributes.get("srcset").and_then(largest_candidate);
+  let src = attributes.get("src").map(String::from);
+
+  // `<source>` of a `<picture>` whose `<img>` has no usable source
+  let picture = node
+    .parent()
+    .filter(|p| is_element(p, "picture"))
+    .and_then(|picture| {
+      picture
+        .children()
+        .filter(|c| is_element(c, "source"))
+        .find_map(|source| {
+          let source = source.as_element()?;
+          let srcset = source.attributes.borrow().get("srcset").map(String::from);
+          srcset.as_deref().and_then(largest_candidate)
+        })
+    });
+
+  lazy
+    .chain(srcset)
+    .chain(src)
+    .chain(picture)
+    .map(|s| s.trim().to_string())
+    .find(|s| !s.is_empty() && !s.starts_with("data:"))
+}
+
+/// Pick the candidate with the highest width or density descriptor from a `srcset`.
+fn largest_candidate(srcset: &str) -> Option<String> {
+  parse_srcset(srcset)
+    .into_iter()
+    .map(|(url, descriptor)| {
+      let size = descriptor
+        .strip_suffix(|c| c == 'w' || c == 'x')
+        .and_then(|n| n.parse::<f32>().ok())
+        .unwrap_or(1.0);
+      (size, url)
+    })
+    .fold(None, |best: Option<(f32, &str)>, (size, url)| match best {
+      Some((s, _)) if s >= size => best,
+      _ => Some((size, url)),
+    })
+    .map(|(_, url)| url.to_string())
+}
+
+/// Split a `srcset` into URLs and their descriptors.
+fn parse_srcset(srcset: &str) -> Vec<(&str, &str)> {
+  let mut ret = vec![];
+  let mut rest = srcset;
+
+  loop {
+    rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    if rest.is_empty() {
+      break;
+    }
+
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (url, tail) = rest.split_at(end);
+
+    // a trailing comma ends a candidate without descriptors
+    let (url, descriptor, tail) = match url.strip_suffix(',') {
+      Some(url) => (url, "", tail),
+      None => {
+        let end = tail.find(',').unwrap_or(tail.len());
+        (url, tail[..end].trim(), &tail[end..])
+      }
+    };
+    rest = tail;
+
+    ret.push((url, descriptor));
+  }
+
+  ret
+}
+
+/// attributes holding a single URL
+const URL_ATTRIBUTES: &[&str] = &[
+  "href",
+  "src",
+  "cite",
+  "poster",
+  "data-src",
+  "data-original",
+  "data-lazy-src",
+];
+
+/// Resolve every relative URL against `base`, the URL the page was served from.
+/// Tracking parameters are stripped from links.
+pub fn absolutize(html: &str, base: &Url) -> String {
+  let document = parse(html);
+
+  for node in document.descendants() {
+    let element = match node.as_element() {
+      Some(element) => element,
+      None => continue,
+    };
+    let mut attributes = element.attributes.borrow_mut();
+
+    for name in URL_ATTRIBUTES {
+      if let Some(value) = attributes.get_mut(*name) {
+        // keep fragments pointing inside the article
+        if value.starts_with('#') || value.trim().is_empty() {
+          continue;
+        }
+        if let Ok(mut url) = base.join(value.trim()) {
+          if *name == "href" {
+            url = strip_tracking(url);
+          }
+          *value = url.into();
+        }
+      }
+    }
+
+    for name in LAZY_SRCSET_ATTRIBUTES.iter().chain(&["srcset"]) {
+      if let Some(value) = attributes.get_mut(*name) {
+        let srcset = parse_srcset(value)
+          .into_iter()
+          .map(|(url, descriptor)| {
+            let url = base
+              .join(url)
+              .map(String::from)
+              .unwrap_or_else(|_| url.into());
+            format!("{} {}", url, descriptor).trim_end().to_string()
+          })
+          .collect::<Vec<_>>()
+          .join(", ");
+        *value = srcset;
+      }
+    }
+  }
+
+  inner_html(&document)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_srcset_splits_candidates() {
+    assert_eq!(
+      parse_srcset("a.jpg 480w, b.jpg 1080w"),
+      vec![("a.jpg", "480w"), ("b.jpg", "1080w")]
+    );
+    assert_eq!(
+      parse_srcset(" a.jpg, b.jpg 2x ,, c.jpg "),
+      vec![("a.jpg", ""), ("b.jpg", "2x"), ("c.jpg", "")]
+    );
+    // commas inside URLs are not separators
+    assert_eq!(
+      parse_srcset("https://cdn.example.com/w_400,h_300/a.jpg 400w"),
+      vec![("https://cdn.example.com/w_400,h_300/a.jpg", "400w")]
+    );
+    assert!(parse_srcset("").is_empty());
+  }
+
+  #[test]
+  fn largest_candidate_prefers_the_widest() {
+    assert_eq!(
+      largest_candidate("a.jpg 480w, b.jpg 1080w, c.jpg 720w").as_deref(),
+      Some("b.jpg")
+    );
+    assert_eq!(
+      largest_candidate("a.jpg, b.jpg 2x").as_deref(),
+      Some("b.jpg")
+    );
+  }
+
+  #[test]
+  fn combine_upright_wraps_short_numbers() {
+    assert_eq!(
+      combine_upright("<p>第12回、2024年、!?と!!!</p>"),
+      r#"<p>第<span class="tcy">12</span>回、2024年、<span class="tcy">!?</span>と!!!</p>"#
+    );
+  }
+
+  #[test]
+  fn combine_upright_skips_numbers_joined_to_words() {
+    assert_eq!(
+      combine_upright("<p>iOS17、3.5倍、1,000円</p>"),
+      "<p>iOS17、3.5倍、1,000円</p>"
+    );
+  }
+
+  #[test]
+  fn combine_upright_skips_code_and_ruby() {
+    assert_eq!(
+      combine_upright("<p><code>a = 1</code><ruby>十<rt>10</rt></ruby></p>"),
+      "<p><code>a = 1</code><ruby>十<rt>10</rt></ruby></p>"
+    );
+  }
+
+  #[test]
+  fn outline_links_headings() {
+    let (html, headings) =
+      outline(r#"<h2 id="intro">Intro</h2><h3>A  b</h3><h2></h2><h3 id="heading-1">C</h3>"#);
+    assert_eq!(
+      html,
+      r#"<h2 id="intro">Intro</h2><h3 id="heading-2">A  b</h3><h2></h2><h3 id="heading-1">C</h3>"#
+    );
+    let headings: Vec<_> = headings
+      .iter()
+      .map(|h| (h.level, h.id.as_str(), h.title.as_str()))
+      .collect();
+    assert_eq!(
+      headings,
+      vec![
+        (2, "intro", "Intro"),
+        (3, "heading-2", "A b"),
+        (3, "heading-1", "C")
+      ]
+    );
+  }
+}
@@ -0,0 +1,5 @@
+pub mod charset;
+pub mod cookies;
+pub mod html;
+pub mod sanitize;
+pub mod url;
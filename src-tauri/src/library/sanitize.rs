@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+
+use kuchikiki::{NodeData, NodeRef};
+
+use super::html::{inner_html, parse, XHTML_NAMESPACE};
+
+/// elements removed together with their contents
+const DROPPED_ELEMENTS: &[&str] = &[
+  "script", "style", "noscript", "template", "iframe", "frame", "frameset", "object", "embed",
+  "applet", "form", "input", "button", "select", "textarea", "canvas", "video", "audio", "source",
+  "track", "map", "link", "meta", "title", "head", "nav",
+];
+
+/// attributes allowed on every element
+const GLOBAL_ATTRIBUTES: &[&str] = &["id", "title", "lang", "dir"];
+
+/// elements kept in the book and their allowed attributes. Other elements are
+/// replaced by their contents.
+const ALLOWED_ELEMENTS: &[(&str, &[&str])] = &[
+  ("p", &[]),
+  ("br", &[]),
+  ("hr", &[]),
+  ("h1", &[]),
+  ("h2", &[]),
+  ("h3", &[]),
+  ("h4", &[]),
+  ("h5", &[]),
+  ("h6", &[]),
+  ("div", &[]),
+  ("span", &[]),
+  ("section", &[]),
+  ("article", &[]),
+  ("aside", &[]),
+  ("header", &[]),
+  ("footer", &[]),
+  ("figure", &[]),
+  ("figcaption", &[]),
+  ("blockquote", &["cite"]),
+  ("pre", &[]),
+  ("code", &[]),
+  ("kbd", &[]),
+  ("samp", &[]),
+  ("var", &[]),
+  ("em", &[]),
+  ("strong", &[]),
+  ("b", &[]),
+  ("i", &[]),
+  ("u", &[]),
+  ("s", &[]),
+  ("del", &["datetime"]),
+  ("ins", &["datetime"]),
+  ("sub", &[]),
+  ("sup", &[]),
+  ("small", &[]),
+  ("mark", &[]),
+  ("abbr", &[]),
+  ("cite", &[]),
+  ("q", &["cite"]),
+  ("dfn", &[]),
+  ("time", &["datetime"]),
+  ("a", &["href"]),
+  ("img", &["src", "alt", "width", "height"]),
+  ("ul", &[]),
+  ("ol", &["start", "reversed"]),
+  ("li", &["value"]),
+  ("dl", &[]),
+  ("dt", &[]),
+  ("dd", &[]),
+  ("table", &[]),
+  ("caption", &[]),
+  ("colgroup", &["span"]),
+  ("col", &["span"]),
+  ("thead", &[]),
+  ("tbody", &[]),
+  ("tfoot", &[]),
+  ("tr", &[]),
+  ("th", &["colspan", "rowspan", "scope"]),
+  ("td", &["colspan", "rowspan"]),
+  ("ruby", &[]),
+  ("rb", &[]),
+  ("rt", &[]),
+  ("rtc", &[]),
+  ("rp", &[]),
+  ("wbr", &[]),
+];
+
+/// attributes which must be a non-negative integer
+const NUMERIC_ATTRIBUTES: &[&str] = &[
+  "width", "height", "start", "value", "span", "colspan", "rowspan",
+];
+
+/// Turn extracted HTML into well-formed XHTML for a chapter of the book.
+/// Only an allowlist of elements and attributes is kept, scripts, embedded
+/// content and event handlers are removed.
+pub fn sanitize(html: &str) -> String {
+  let document = parse(html);
+  if let Ok(body) = document.select_first("body") {
+    clean(body.as_node(), &mut HashSet::new());
+  }
+  inner_html(&document)
+}
+
+fn clean(node: &NodeRef, ids: &mut HashSet<String>) {
+  // collect first as the tree is modified while iterating
+  let children: Vec<_> = node.children().collect();
+
+  for child in children {
+    let element = match child.data() {
+      NodeData::Element(element) => element,
+      NodeData::Text(_) => continue,
+      _ => {
+        child.detach();
+        continue;
+      }
+    };
+
+    let name = element.name.local.to_string();
+    // inline SVG and MathML
+    if &*element.name.ns != XHTML_NAMESPACE || DROPPED_ELEMENTS.contains(&name.as_str()) {
+      child.detach();
+      continue;
+    }
+
+    let allowed = ALLOWED_ELEMENTS
+      .iter()
+      .find(|(n, _)| *n == name)
+      .map(|(_, attributes)| *attributes);
+
+    let allowed = match allowed {
+      Some(allowed) => allowed,
+      None => {
+        clean(&child, ids);
+        while let Some(c) = child.first_child() {
+          child.insert_before(c);
+        }
+        child.detach();
+        continue;
+      }
+    };
+
+    let mut attributes = element.attributes.borrow_mut();
+    attributes.map.retain(|attribute, value| {
+      let attribute = &*attribute.local;
+      let value = value.value.trim();
+
+      if !GLOBAL_ATTRIBUTES.contains(&attribute) && !allowed.contains(&attribute) {
+        return false;
+      }
+
+      match attribute {
+        "id" => {
+          !value.is_empty() && !value.contains(char::is_whitespace) && ids.insert(value.into())
+        }
+        "dir" => matches!(value, "ltr" | "rtl" | "auto"),
+        "href" | "src" | "cite" => is_safe_url(value),
+        _ if NUMERIC_ATTRIBUTES.contains(&attribute) => {
+          !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+        }
+        _ => true,
+      }
+    });
+
+    if name == "img" {
+      if !attributes.contains("src") {
+        drop(attributes);
+        child.detach();
+        continue;
+      }
+      // required by EPUB
+      if !attributes.contains("alt") {
+        attributes.insert("alt", String::new());
+      }
+    }
+    drop(attributes);
+
+    clean(&child, ids);
+  }
+}
+
+fn is_safe_url(url: &str) -> bool {
+  let scheme = url
+    .split_once(':')
+    .map(|(scheme, _)| scheme)
+    .filter(|scheme| !scheme.contains(['/', '?', '#']))
+    .map(|scheme| scheme.to_ascii_lowercase());
+
+  match scheme.as_deref() {
+    Some(scheme) => matches!(scheme, "http" | "https" | "mailto"),
+    // relative
+    None => true,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sanitize_removes_scripts_and_embedded_content() {
+    assert_eq!(
+      sanitize(r#"<p>a<script>alert(1)</script></p><iframe src="x"></iframe><svg><g /></svg>"#),
+      "<p>a</p>"
+    );
+  }
+
+  #[test]
+  fn sanitize_unwraps_unknown_elements() {
+    assert_eq!(
+      sanitize("<custom-card><p>a <font color=red>b</font></p></custom-card>"),
+      "<p>a b</p>"
+    );
+  }
+
+  #[test]
+  fn sanitize_filters_attributes() {
+    assert_eq!(
+      sanitize(
+        r#"<p onclick="x()" style="color: red" dir="sideways" lang="en">a</p><a href="javascript:x()">b</a><a href="/c">c</a>"#
+      ),
+      r#"<p lang="en">a</p><a>b</a><a href="/c">c</a>"#
+    );
+    assert_eq!(
+      sanitize(r#"<table><tr><td colspan="2" rowspan="x">a</td></tr></table>"#),
+      r#"<table><tbody><tr><td colspan="2">a</td></tr></tbody></table>"#
+    );
+    assert_eq!(
+      sanitize(r#"<ol start="3"><li value="-1">b</li></ol>"#),
+      r#"<ol start="3"><li>b</li></ol>"#
+    );
+  }
+
+  #[test]
+  fn sanitize_keeps_ids_unique() {
+    assert_eq!(
+      sanitize(r#"<p id="a">1</p><p id="a">2</p><p id="b c">3</p>"#),
+      r#"<p id="a">1</p><p>2</p><p>3</p>"#
+    );
+  }
+
+  #[test]
+  fn sanitize_fixes_images() {
+    assert_eq!(
+      sanitize(r#"<img src="a.png"><img alt="no source">"#),
+      r#"<img src="a.png" alt="" />"#
+    );
+  }
+
+  #[test]
+  fn sanitize_keeps_ruby() {
+    assert_eq!(
+      sanitize("<ruby><rb>漢</rb><rp>(</rp><rt>かん</rt><rp>)</rp></ruby>"),
+      "<ruby><rb>漢</rb><rp>(</rp><rt>かん</rt><rp>)</rp></ruby>"
+    );
+  }
+}
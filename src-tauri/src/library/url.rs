@@ -0,0 +1,100 @@
+use url::Url;
+
+/// query parameters added by analytics and ad platforms
+const TRACKING_PARAMETERS: &[&str] = &[
+  "fbclid",
+  "gclid",
+  "dclid",
+  "gbraid",
+  "wbraid",
+  "msclkid",
+  "yclid",
+  "igshid",
+  "mc_cid",
+  "mc_eid",
+  "_ga",
+  "_gl",
+  "ref_src",
+  "ref_url",
+  "__twitter_impression",
+];
+
+/// Remove tracking parameters such as `utm_*` and `fbclid` from the query.
+pub fn strip_tracking(mut url: Url) -> Url {
+  if url.query().is_none() {
+    return url;
+  }
+
+  let pairs = url.query_pairs().count();
+  let query: Vec<(String, String)> = url
+    .query_pairs()
+    .filter(|(key, _)| {
+      let key = key.to_ascii_lowercase();
+      !key.starts_with("utm_") && !TRACKING_PARAMETERS.contains(&key.as_str())
+    })
+    .map(|(key, value)| (key.into_owned(), value.into_owned()))
+    .collect();
+
+  // leave the encoding of untouched queries as it is
+  if query.len() == pairs {
+    return url;
+  }
+
+  if query.is_empty() {
+    url.set_query(None);
+  } else {
+    url.query_pairs_mut().clear().extend_pairs(query);
+  }
+  url
+}
+
+/// Normalize `url` for use as a cache key: without fragment and tracking
+/// parameters.
+pub fn normalize(url: &str) -> String {
+  match Url::parse(url) {
+    Ok(mut url) => {
+      url.set_fragment(None);
+      strip_tracking(url).into()
+    }
+    Err(_) => url.trim().into(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn strip(url: &str) -> String {
+    strip_tracking(Url::parse(url).unwrap()).into()
+  }
+
+  #[test]
+  fn strip_tracking_removes_tracking_parameters() {
+    assert_eq!(
+      strip("https://example.com/a?id=1&utm_source=x&UTM_Medium=y&fbclid=z"),
+      "https://example.com/a?id=1"
+    );
+    assert_eq!(
+      strip("https://example.com/a?utm_source=x#top"),
+      "https://example.com/a#top"
+    );
+  }
+
+  #[test]
+  fn strip_tracking_keeps_other_queries_as_they_are() {
+    assert_eq!(
+      strip("https://example.com/a?q=a%20b&page=2"),
+      "https://example.com/a?q=a%20b&page=2"
+    );
+    assert_eq!(strip("https://example.com/a"), "https://example.com/a");
+  }
+
+  #[test]
+  fn normalize_drops_the_fragment() {
+    assert_eq!(
+      normalize("https://example.com/a?gclid=1#section"),
+      "https://example.com/a"
+    );
+    assert_eq!(normalize(" not a url "), "not a url");
+  }
+}
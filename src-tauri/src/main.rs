@@ -32,6 +32,11 @@ fn main() {
       command::logout,
       command::list,
       command::send,
+      command::list_credentials,
+      command::import_cookies,
+      command::set_headers,
+      command::remove_credential,
+      command::clear_cache,
     ])
     .setup(|app| {
       let _window = WindowBuilder::new(app, "main", WindowUrl::default())
@@ -0,0 +1,486 @@
+use std::{
+  fs::{self, File},
+  io::{Read, Write},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{
+  header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+  RequestBuilder, Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use shaku::Component;
+
+use crate::domain::repository::cache::{CacheEntry, CacheKind, CacheRepository, ClearOutput};
+use crate::library::url::normalize;
+
+pub struct CacheRepositoryConfig {
+  pub dir: PathBuf,
+  /// least recently used entries are removed beyond this many bytes
+  pub max_size: u64,
+  /// entries younger than this are used without asking the server
+  pub fresh_for: Duration,
+}
+
+impl Default for CacheRepositoryConfig {
+  fn default() -> Self {
+    Self {
+      dir: PathBuf::new(),
+      max_size: 256 * 1024 * 1024,
+      fresh_for: Duration::from_secs(60 * 60),
+    }
+  }
+}
+
+/// Each entry is a file holding a line of JSON metadata followed by the body.
+/// The modification time of the file records when it was last used.
+///
+/// The files are read and written on the blocking thread pool.
+#[derive(Component)]
+#[shaku(interface = CacheRepository)]
+pub struct CacheRepositoryImpl {
+  config: Arc<CacheRepositoryConfig>,
+  /// total size of the entries in bytes
+  size: Arc<Mutex<u64>>,
+}
+
+#[async_trait]
+impl CacheRepository for CacheRepositoryImpl {
+  async fn get(&self, kind: CacheKind, url: &str) -> Option<CacheEntry> {
+    let url = url.to_string();
+    let ret = self.blocking(move |cache| cache.read(kind, &url)).await;
+    ret.ok().flatten()
+  }
+
+  async fn put(&self, kind: CacheKind, url: &str, entry: &CacheEntry) -> Result<()> {
+    let url = url.to_string();
+    let entry = entry.clone();
+    self
+      .blocking(move |cache| cache.write(kind, &url, &entry))
+      .await?
+  }
+
+  async fn clear(&self) -> Result<ClearOutput> {
+    self.blocking(|cache| cache.remove_all()).await?
+  }
+}
+
+impl CacheRepositoryImpl {
+  pub fn new(config: CacheRepositoryConfig) -> Result<Self> {
+    for kind in CacheKind::ALL {
+      fs::create_dir_all(config.dir.join(kind.name()))?;
+    }
+
+    let repository = Self {
+      config: Arc::new(config),
+      size: Arc::new(Mutex::new(0)),
+    };
+    *repository.size.lock().unwrap() = repository.files()?.iter().map(|(_, len, _)| len).sum();
+
+    Ok(repository)
+  }
+
+  /// Run `f` with this cache on the blocking thread pool.
+  async fn blocking<T, F>(&self, f: F) -> Result<T>
+  where
+    F: FnOnce(Self) -> T + Send + 'static,
+    T: Send + 'static,
+  {
+    let cache = Self {
+      config: self.config.clone(),
+      size: self.size.clone(),
+    };
+    Ok(tokio::task::spawn_blocking(move || f(cache)).await?)
+  }
+
+  fn read(&self, kind: CacheKind, url: &str) -> Option<CacheEntry> {
+    let path = self.path(kind, url);
+    // writable to mark the entry as used
+    let mut file = File::options().read(true).write(true).open(&path).ok()?;
+    let mut bytes = vec![];
+    if let Err(e) = file.read_to_end(&mut bytes) {
+      log::warn!("failed to read {}: {}", path.display(), e);
+      return None;
+    }
+
+    let (mut entry, data) = match decode(&bytes) {
+      Ok(ret) => ret,
+      Err(e) => {
+        log::warn!("invalid cache entry {}: {:#}", path.display(), e);
+        return None;
+      }
+    };
+    entry.fresh = now().saturating_sub(entry.fetched_at) < self.config.fresh_for.as_secs();
+    entry.data = data.to_vec();
+
+    if let Err(e) = file.set_modified(SystemTime::now()) {
+      log::debug!("failed to touch {}: {}", path.display(), e);
+    }
+
+    Some(entry)
+  }
+
+  fn write(&self, kind: CacheKind, url: &str, entry: &CacheEntry) -> Result<()> {
+    let path = self.path(kind, url);
+    let bytes = encode(&CacheEntry {
+      fetched_at: now(),
+      ..entry.clone()
+    })?;
+
+    let mut size = self.size.lock().unwrap();
+    let old = fs::metadata(&path).map_or(0, |m| m.len());
+
+    let tmp = path.with_extension("tmp");
+    File::create(&tmp)?.write_all(&bytes)?;
+    fs::rename(&tmp, &path)?;
+
+    *size = (*size + bytes.len() as u64).saturating_sub(old);
+    if *size > self.config.max_size {
+      *size = self.evict(self.config.max_size / 10 * 9)?;
+    }
+
+    Ok(())
+  }
+
+  fn remove_all(&self) -> Result<ClearOutput> {
+    let mut size = self.size.lock().unwrap();
+
+    let files = self.files()?;
+    let ret = ClearOutput {
+      entries: files.len(),
+      size: files.iter().map(|(_, len, _)| len).sum(),
+    };
+    for (path, _, _) in files {
+      fs::remove_file(path)?;
+    }
+    *size = 0;
+
+    log::info!("cleared {} cache entries ({} bytes)", ret.entries, ret.size);
+
+    Ok(ret)
+  }
+
+  fn path(&self, kind: CacheKind, url: &str) -> PathBuf {
+    let hash = Sha256::digest(normalize(url).as_bytes());
+    self
+      .config
+      .dir
+      .join(kind.name())
+      .join(format!("{:x}", hash))
+  }
+
+  /// All entries as (path, size, last used).
+  fn files(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut files = vec![];
+    for kind in CacheKind::ALL {
+      for file in fs::read_dir(self.config.dir.join(kind.name()))? {
+        let file = file?;
+        let metadata = file.metadata()?;
+        if metadata.is_file() {
+          files.push((file.path(), metadata.len(), metadata.modified()?));
+        }
+      }
+    }
+    Ok(files)
+  }
+
+  /// Remove least recently used entries until at most `target` bytes remain,
+  /// and return the remaining size.
+  fn evict(&self, target: u64) -> Result<u64> {
+    let mut files = self.files()?;
+    files.sort_by_key(|(_, _, used)| *used);
+
+    let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+    for (path, len, _) in files {
+      if size <= target {
+        break;
+      }
+      remove(&path)?;
+      size -= len;
+    }
+
+    Ok(size)
+  }
+}
+
+/// A response from the cache or the server.
+pub struct Cached {
+  pub entry: CacheEntry,
+  /// whether the response differs from the cached one
+  pub changed: bool,
+}
+
+/// Send `builder` as a conditional request if a cached response can be
+/// reused. `None` if the server reports `cached` unchanged.
+pub async fn conditional_get(
+  builder: RequestBuilder,
+  cached: Option<&CacheEntry>,
+) -> Result<Option<Response>> {
+  let res = revalidate(builder, cached).send().await?;
+  if res.status() == StatusCode::NOT_MODIFIED {
+    return Ok(None);
+  }
+  Ok(Some(res.error_for_status()?))
+}
+
+/// Store the `downloaded` response of `url`, which is `None` if `cached` is
+/// still valid. `cached` is also used when the server can't be reached.
+pub async fn revalidated(
+  cache: &dyn CacheRepository,
+  kind: CacheKind,
+  url: &str,
+  downloaded: Result<Option<CacheEntry>>,
+  cached: Option<CacheEntry>,
+) -> Result<Cached> {
+  match (downloaded, cached) {
+    (Ok(Some(entry)), _) => {
+      store(cache, kind, url, &entry).await;
+      Ok(Cached {
+        entry,
+        changed: true,
+      })
+    }
+    (Ok(None), Some(cached)) => {
+      // not modified, so it is fresh again
+      store(cache, kind, url, &cached).await;
+      Ok(Cached {
+        entry: cached,
+        changed: false,
+      })
+    }
+    (Ok(None), None) => bail!("{} is not modified but not cached", url),
+    (Err(e), Some(cached)) => {
+      log::warn!("using cached {}: {:#}", url, e);
+      Ok(Cached {
+        entry: cached,
+        changed: false,
+      })
+    }
+    (Err(e), None) => Err(e),
+  }
+}
+
+/// Store `entry`, logging failures as the response can be used anyway.
+pub async fn store(cache: &dyn CacheRepository, kind: CacheKind, url: &str, entry: &CacheEntry) {
+  if let Err(e) = cache.put(kind, url, entry).await {
+    log::warn!("failed to cache {}: {:#}", url, e);
+  }
+}
+
+/// Make `builder` a conditional request if a cached response can be reused.
+fn revalidate(mut builder: RequestBuilder, cached: Option<&CacheEntry>) -> RequestBuilder {
+  if let Some(cached) = cached {
+    if let Some(etag) = &cached.etag {
+      builder = builder.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &cached.last_modified {
+      builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+    }
+  }
+  builder
+}
+
+/// Metadata of `res` to be stored with its body.
+pub fn entry_of(res: &Response) -> CacheEntry {
+  let header = |name| {
+    res
+      .headers()
+      .get(name)
+      .and_then(|v| v.to_str().ok())
+      .map(String::from)
+  };
+
+  CacheEntry {
+    url: res.url().to_string(),
+    content_type: header(CONTENT_TYPE),
+    etag: header(ETAG),
+    last_modified: header(LAST_MODIFIED),
+    ..Default::default()
+  }
+}
+
+fn remove(path: &Path) -> Result<()> {
+  log::debug!("evicting {}", path.display());
+  fs::remove_file(path).with_context(|| format!("failed to remove {}", path.display()))
+}
+
+fn encode(entry: &CacheEntry) -> Result<Vec<u8>> {
+  let mut bytes = serde_json::to_vec(entry)?;
+  bytes.push(b'\n');
+  bytes.extend_from_slice(&entry.data);
+  Ok(bytes)
+}
+
+fn decode(bytes: &[u8]) -> Result<(CacheEntry, &[u8])> {
+  let end = bytes
+    .iter()
+    .position(|b| *b == b'\n')
+    .context("metadata not found")?;
+  Ok((serde_json::from_slice(&bytes[..end])?, &bytes[end + 1..]))
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+  use anyhow::anyhow;
+  use tempfile::TempDir;
+
+  use super::*;
+
+  const URL: &str = "https://example.com/a";
+
+  fn repository(dir: &TempDir, max_size: u64, fresh_for: Duration) -> CacheRepositoryImpl {
+    CacheRepositoryImpl::new(CacheRepositoryConfig {
+      dir: dir.path().into(),
+      max_size,
+      fresh_for,
+    })
+    .unwrap()
+  }
+
+  fn entry(data: &str) -> CacheEntry {
+    CacheEntry {
+      url: URL.into(),
+      etag: Some("\"v1\"".into()),
+      data: data.into(),
+      ..Default::default()
+    }
+  }
+
+  /// Mark the entry of `url` as last used `secs` seconds ago.
+  fn used_ago(cache: &CacheRepositoryImpl, url: &str, secs: u64) {
+    File::options()
+      .write(true)
+      .open(cache.path(CacheKind::Page, url))
+      .unwrap()
+      .set_modified(SystemTime::now() - Duration::from_secs(secs))
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn put_evicts_least_recently_used_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let urls = [
+      "https://example.com/a",
+      "https://example.com/b",
+      "https://example.com/c",
+    ];
+
+    // the entries are the same size, so two and a half fit
+    let cache = repository(&dir, u64::MAX, Duration::ZERO);
+    cache
+      .put(CacheKind::Page, urls[0], &entry("x"))
+      .await
+      .unwrap();
+    let size = *cache.size.lock().unwrap();
+    let cache = repository(&dir, size * 5 / 2, Duration::ZERO);
+    assert_eq!(*cache.size.lock().unwrap(), size);
+
+    cache
+      .put(CacheKind::Page, urls[1], &entry("x"))
+      .await
+      .unwrap();
+    used_ago(&cache, urls[0], 20);
+    used_ago(&cache, urls[1], 10);
+    assert!(cache.get(CacheKind::Page, urls[0]).await.is_some());
+
+    cache
+      .put(CacheKind::Page, urls[2], &entry("x"))
+      .await
+      .unwrap();
+
+    // 90% of the limit only holds two
+    assert_eq!(*cache.size.lock().unwrap(), size * 2);
+    assert!(cache.get(CacheKind::Page, urls[0]).await.is_some());
+    assert!(cache.get(CacheKind::Page, urls[1]).await.is_none());
+    assert!(cache.get(CacheKind::Page, urls[2]).await.is_some());
+  }
+
+  #[tokio::test]
+  async fn get_marks_recent_entries_fresh() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = repository(&dir, u64::MAX, Duration::from_secs(60));
+    cache.put(CacheKind::Page, URL, &entry("a")).await.unwrap();
+
+    let cached = cache.get(CacheKind::Page, URL).await.unwrap();
+    assert!(cached.fresh);
+    assert_eq!(cached.data, b"a");
+    assert_eq!(cached.etag.as_deref(), Some("\"v1\""));
+
+    let cache = repository(&dir, u64::MAX, Duration::ZERO);
+    assert!(!cache.get(CacheKind::Page, URL).await.unwrap().fresh);
+  }
+
+  #[tokio::test]
+  async fn revalidated_refreshes_unmodified_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = repository(&dir, u64::MAX, Duration::from_secs(60));
+    // fetched long ago
+    fs::write(
+      cache.path(CacheKind::Page, URL),
+      encode(&entry("a")).unwrap(),
+    )
+    .unwrap();
+    let cached = cache.get(CacheKind::Page, URL).await.unwrap();
+    assert!(!cached.fresh);
+
+    let ret = revalidated(&cache, CacheKind::Page, URL, Ok(None), Some(cached))
+      .await
+      .unwrap();
+
+    assert!(!ret.changed);
+    assert_eq!(ret.entry.data, b"a");
+    assert!(cache.get(CacheKind::Page, URL).await.unwrap().fresh);
+  }
+
+  #[tokio::test]
+  async fn revalidated_stores_changed_entries() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = repository(&dir, u64::MAX, Duration::from_secs(60));
+    cache.put(CacheKind::Page, URL, &entry("a")).await.unwrap();
+    let cached = cache.get(CacheKind::Page, URL).await;
+
+    let ret = revalidated(&cache, CacheKind::Page, URL, Ok(Some(entry("b"))), cached)
+      .await
+      .unwrap();
+
+    assert!(ret.changed);
+    assert_eq!(ret.entry.data, b"b");
+    assert_eq!(cache.get(CacheKind::Page, URL).await.unwrap().data, b"b");
+  }
+
+  #[tokio::test]
+  async fn revalidated_falls_back_to_the_cache_offline() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = repository(&dir, u64::MAX, Duration::ZERO);
+    cache.put(CacheKind::Page, URL, &entry("a")).await.unwrap();
+    let cached = cache.get(CacheKind::Page, URL).await;
+
+    let ret = revalidated(
+      &cache,
+      CacheKind::Page,
+      URL,
+      Err(anyhow!("offline")),
+      cached,
+    )
+    .await
+    .unwrap();
+    assert!(!ret.changed);
+    assert_eq!(ret.entry.data, b"a");
+
+    let e = revalidated(&cache, CacheKind::Image, URL, Err(anyhow!("offline")), None)
+      .await
+      .err()
+      .unwrap();
+    assert_eq!(e.to_string(), "offline");
+  }
+}
@@ -0,0 +1,161 @@
+use std::{
+  fs::{self, OpenOptions},
+  io::{ErrorKind, Write},
+  path::PathBuf,
+  sync::RwLock,
+};
+
+use anyhow::{Context, Result};
+use shaku::Component;
+use url::Url;
+
+use crate::domain::repository::credential::{
+  Credential, CredentialRepository, ImportCookiesInput, ImportCookiesOutput, RemoveInput,
+  SetHeadersInput,
+};
+use crate::library::cookies::{cookie_header, parse_netscape};
+
+#[derive(Default)]
+pub struct CredentialRepositoryConfig {
+  /// readable only by the user, as it holds session cookies and tokens
+  pub file_path: PathBuf,
+}
+
+#[derive(Component)]
+#[shaku(interface = CredentialRepository)]
+pub struct CredentialRepositoryImpl {
+  config: CredentialRepositoryConfig,
+  credentials: RwLock<Vec<Credential>>,
+}
+
+impl CredentialRepository for CredentialRepositoryImpl {
+  fn list(&self) -> Vec<Credential> {
+    self.credentials.read().unwrap().clone()
+  }
+
+  fn import_cookies(&self, input: ImportCookiesInput) -> Result<ImportCookiesOutput> {
+    let cookies = parse_netscape(&input.contents)?;
+
+    let mut credentials = self.credentials.write().unwrap();
+    let mut domains: Vec<String> = vec![];
+    for cookie in &cookies {
+      let credential = entry(&mut credentials, &cookie.domain);
+      credential.cookies.retain(|c| !c.same(cookie));
+      credential.cookies.push(cookie.clone());
+
+      if !domains.contains(&cookie.domain) {
+        domains.push(cookie.domain.clone());
+      }
+    }
+    self.save(&credentials)?;
+
+    Ok(ImportCookiesOutput {
+      domains,
+      cookies: cookies.len(),
+    })
+  }
+
+  fn set_headers(&self, input: SetHeadersInput) -> Result<()> {
+    let domain = normalize_domain(&input.domain);
+    if domain.is_empty() {
+      anyhow::bail!("domain is empty");
+    }
+
+    let mut credentials = self.credentials.write().unwrap();
+    entry(&mut credentials, &domain).headers = input.headers;
+    credentials.retain(|c| !c.headers.is_empty() || !c.cookies.is_empty());
+    self.save(&credentials)
+  }
+
+  fn remove(&self, input: RemoveInput) -> Result<()> {
+    let domain = normalize_domain(&input.domain);
+
+    let mut credentials = self.credentials.write().unwrap();
+    credentials.retain(|c| c.domain != domain);
+    self.save(&credentials)
+  }
+
+  fn headers(&self, url: &Url) -> Vec<(String, String)> {
+    let host = match url.host_str() {
+      Some(host) => host.to_lowercase(),
+      None => return vec![],
+    };
+
+    let credentials = self.credentials.read().unwrap();
+    let mut headers: Vec<(String, String)> = credentials
+      .iter()
+      .filter(|c| host == c.domain || host.ends_with(&format!(".{}", c.domain)))
+      .flat_map(|c| c.headers.iter().map(|(k, v)| (k.clone(), v.clone())))
+      .collect();
+
+    if let Some(cookie) = cookie_header(credentials.iter().flat_map(|c| &c.cookies), url) {
+      headers.push(("Cookie".into(), cookie));
+    }
+
+    headers
+  }
+}
+
+impl CredentialRepositoryImpl {
+  pub fn new(config: CredentialRepositoryConfig) -> Result<Self> {
+    // any other error must not be taken for an empty file, which the next
+    // save would overwrite
+    let credentials = match fs::read_to_string(&config.file_path) {
+      Ok(json) => serde_json::from_str(&json)
+        .with_context(|| format!("invalid {}", config.file_path.display()))?,
+      Err(e) if e.kind() == ErrorKind::NotFound => vec![],
+      Err(e) => {
+        return Err(e).with_context(|| format!("failed to read {}", config.file_path.display()))
+      }
+    };
+
+    Ok(Self {
+      config,
+      credentials: RwLock::new(credentials),
+    })
+  }
+
+  /// Replace the file atomically, so a crash never leaves it half written.
+  fn save(&self, credentials: &[Credential]) -> Result<()> {
+    let tmp = self.config.file_path.with_extension("tmp");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::OpenOptionsExt;
+      options.mode(0o600);
+    }
+
+    let mut file = options.open(&tmp)?;
+    // the mode only applies to new files, and a file left by a crash may have
+    // been created otherwise
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(&serde_json::to_vec(credentials)?)?;
+    file.sync_all()?;
+    fs::rename(&tmp, &self.config.file_path)?;
+
+    Ok(())
+  }
+}
+
+fn normalize_domain(domain: &str) -> String {
+  domain.trim().trim_start_matches('.').to_lowercase()
+}
+
+fn entry<'a>(credentials: &'a mut Vec<Credential>, domain: &str) -> &'a mut Credential {
+  match credentials.iter().position(|c| c.domain == domain) {
+    Some(i) => &mut credentials[i],
+    None => {
+      credentials.push(Credential {
+        domain: domain.into(),
+        ..Default::default()
+      });
+      credentials.last_mut().unwrap()
+    }
+  }
+}
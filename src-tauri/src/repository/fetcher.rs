@@ -0,0 +1,101 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use reqwest::{redirect, Client, Proxy, RequestBuilder};
+use shaku::{Component, Interface};
+use url::Url;
+
+use crate::domain::repository::credential::CredentialRepository;
+
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) \
+  AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Safari/605.1.15";
+
+/// Shared HTTP client of the repositories.
+pub trait Fetcher: Interface {
+  fn client(&self) -> &Client;
+
+  /// `GET` with the headers and cookies of the credentials of the domain of
+  /// `url`. Cookies set by the sites are added by the client.
+  fn get(&self, url: &str) -> RequestBuilder;
+}
+
+pub struct FetcherConfig {
+  pub user_agent: String,
+  pub connect_timeout: Duration,
+  /// limit for a whole request including the body
+  pub timeout: Duration,
+  /// `http://`, `https://` or `socks5://` proxy for all requests
+  pub proxy: Option<String>,
+  pub pool_max_idle_per_host: usize,
+  pub pool_idle_timeout: Duration,
+  pub max_redirects: usize,
+}
+
+impl Default for FetcherConfig {
+  fn default() -> Self {
+    Self {
+      user_agent: DEFAULT_USER_AGENT.into(),
+      connect_timeout: Duration::from_secs(10),
+      timeout: Duration::from_secs(60),
+      proxy: None,
+      pool_max_idle_per_host: 8,
+      pool_idle_timeout: Duration::from_secs(90),
+      max_redirects: 10,
+    }
+  }
+}
+
+#[derive(Component)]
+#[shaku(interface = Fetcher)]
+pub struct FetcherImpl {
+  #[shaku(inject)]
+  credentials: Arc<dyn CredentialRepository>,
+  client: Client,
+}
+
+impl Fetcher for FetcherImpl {
+  fn client(&self) -> &Client {
+    &self.client
+  }
+
+  fn get(&self, url: &str) -> RequestBuilder {
+    let mut builder = self.client.get(url);
+
+    let url = match Url::parse(url) {
+      Ok(url) => url,
+      Err(_) => return builder,
+    };
+
+    // per-domain headers and cookies are set in the credentials only
+    for (name, value) in self.credentials.headers(&url) {
+      builder = builder.header(name, value);
+    }
+
+    builder
+  }
+}
+
+impl FetcherImpl {
+  /// Build the client up front, so that an invalid proxy fails at startup.
+  pub fn parameters(config: FetcherConfig) -> Result<FetcherImplParameters> {
+    let mut builder = Client::builder()
+      .user_agent(&config.user_agent)
+      .connect_timeout(config.connect_timeout)
+      .timeout(config.timeout)
+      .pool_max_idle_per_host(config.pool_max_idle_per_host)
+      .pool_idle_timeout(config.pool_idle_timeout)
+      .redirect(redirect::Policy::limited(config.max_redirects))
+      .cookie_store(true)
+      .gzip(true)
+      .brotli(true)
+      .deflate(true);
+
+    if let Some(proxy) = &config.proxy {
+      builder = builder.proxy(Proxy::all(proxy)?);
+    }
+
+    Ok(FetcherImplParameters {
+      client: builder.build()?,
+    })
+  }
+}
@@ -0,0 +1,123 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use shaku::Component;
+use tokio::sync::Semaphore;
+
+use crate::domain::repository::{
+  cache::{CacheEntry, CacheKind, CacheRepository},
+  image::{FetchInput, FetchOutput, ImageRepository},
+};
+use crate::repository::{
+  cache::{conditional_get, entry_of, revalidated},
+  fetcher::Fetcher,
+};
+
+pub struct ImageRepositoryConfig {
+  pub timeout: Duration,
+  /// images larger than this are skipped
+  pub max_size: usize,
+  /// maximum number of images downloaded at the same time
+  pub concurrency: usize,
+}
+
+impl Default for ImageRepositoryConfig {
+  fn default() -> Self {
+    Self {
+      timeout: Duration::from_secs(30),
+      max_size: 10 * 1024 * 1024,
+      concurrency: 8,
+    }
+  }
+}
+
+#[derive(Component)]
+#[shaku(interface = ImageRepository)]
+pub struct ImageRepositoryImpl {
+  config: ImageRepositoryConfig,
+  fetcher: Arc<dyn Fetcher>,
+  cache: Arc<dyn CacheRepository>,
+  permits: Semaphore,
+}
+
+#[async_trait]
+impl ImageRepository for ImageRepositoryImpl {
+  async fn fetch(&self, input: FetchInput) -> Result<FetchOutput> {
+    let cached = match self.cache.get(CacheKind::Image, &input.url).await {
+      Some(cached) if cached.fresh => return Ok(output_of(cached)),
+      cached => cached,
+    };
+
+    let _permit = self.permits.acquire().await?;
+
+    let downloaded = self.download(&input.url, cached.as_ref()).await;
+    let ret = revalidated(
+      self.cache.as_ref(),
+      CacheKind::Image,
+      &input.url,
+      downloaded,
+      cached,
+    )
+    .await?;
+    Ok(output_of(ret.entry))
+  }
+}
+
+impl ImageRepositoryImpl {
+  pub fn new(
+    fetcher: Arc<dyn Fetcher>,
+    cache: Arc<dyn CacheRepository>,
+    config: ImageRepositoryConfig,
+  ) -> Self {
+    Self {
+      permits: Semaphore::new(config.concurrency.max(1)),
+      fetcher,
+      cache,
+      config,
+    }
+  }
+
+  /// Download the image, or `None` if `cached` is still valid.
+  async fn download(&self, url: &str, cached: Option<&CacheEntry>) -> Result<Option<CacheEntry>> {
+    let builder = self.fetcher.get(url).timeout(self.config.timeout);
+    let Some(res) = conditional_get(builder, cached).await? else {
+      return Ok(None);
+    };
+
+    let mut entry = entry_of(&res);
+    let mime = mime_of(&entry);
+    if !mime.starts_with("image/") {
+      bail!("{} is not an image: {:?}", url, mime);
+    }
+
+    if res.content_length().unwrap_or(0) as usize > self.config.max_size {
+      bail!("{} is too large", url);
+    }
+    entry.data = res.bytes().await?.to_vec();
+    if entry.data.len() > self.config.max_size {
+      bail!("{} is too large", url);
+    }
+
+    log::debug!("fetched {} ({}, {} bytes)", url, mime, entry.data.len());
+
+    Ok(Some(entry))
+  }
+}
+
+/// The MIME type without parameters, e.g. `image/png`.
+fn mime_of(entry: &CacheEntry) -> String {
+  entry
+    .content_type
+    .as_deref()
+    .and_then(|v| v.split(';').next())
+    .map(|v| v.trim().to_lowercase())
+    .unwrap_or_default()
+}
+
+fn output_of(entry: CacheEntry) -> FetchOutput {
+  FetchOutput {
+    mime: mime_of(&entry),
+    data: entry.data,
+  }
+}
@@ -0,0 +1,218 @@
+use serde::Deserialize;
+
+use super::chapter::{host_name, site_name};
+use crate::domain::{Article, DeliveryMode};
+
+/// How the articles of a book are grouped into sections.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Sections {
+  None,
+  /// by the first tag
+  Tag,
+  /// by the site name, or the host of the URL
+  Site,
+  /// by the host of the URL, which keeps together the publications of a
+  /// platform such as medium.com that have their own site names
+  Source,
+}
+
+/// Metadata of the generated books.
+pub struct BookOptions {
+  /// title of digests, where `{date}` is replaced with the date of the build,
+  /// e.g. `Reading list – {date}`
+  pub title: String,
+  /// title of the books of a tag, where `{tag}` is also replaced
+  pub tag_title: String,
+  /// author of the books holding several articles. A book of an article has
+  /// the author of the article
+  pub author: String,
+  pub publisher: String,
+  pub sections: Sections,
+}
+
+impl Default for BookOptions {
+  fn default() -> Self {
+    Self {
+      title: "Pocket {date}".into(),
+      tag_title: "Pocket {date}: {tag}".into(),
+      author: "Pocket".into(),
+      publisher: "Send Pocket to Kindle".into(),
+      // most articles of a digest come from different sites, which would add
+      // a section page before nearly every article
+      sections: Sections::None,
+    }
+  }
+}
+
+impl BookOptions {
+  pub fn title(&self, date: &str) -> String {
+    self.title.replace("{date}", date)
+  }
+
+  pub fn tag_title(&self, date: &str, tag: &str) -> String {
+    self.tag_title.replace("{date}", date).replace("{tag}", tag)
+  }
+
+  /// The section of `article`, `None` if books have no sections.
+  pub fn section(&self, article: &Article) -> Option<String> {
+    match self.sections {
+      Sections::None => None,
+      Sections::Tag => Some(
+        article
+          .tags
+          .first()
+          .cloned()
+          .unwrap_or_else(|| "Untagged".into()),
+      ),
+      Sections::Site => Some(site_name(article).unwrap_or_else(|| "Other".into())),
+      Sections::Source => Some(host_name(article).unwrap_or_else(|| "Other".into())),
+    }
+  }
+
+  /// Keep the articles of a section together, in the order of the first
+  /// article of each section.
+  fn sort_by_section(&self, articles: &[Article], indices: &mut [usize]) {
+    let mut sections: Vec<Option<String>> = vec![];
+    for &i in indices.iter() {
+      let section = self.section(&articles[i]);
+      if !sections.contains(&section) {
+        sections.push(section);
+      }
+    }
+    indices.sort_by_key(|&i| {
+      let section = self.section(&articles[i]);
+      sections.iter().position(|s| *s == section)
+    });
+  }
+}
+
+/// Articles of a book, by their index.
+pub struct Group {
+  pub title: String,
+  /// empty if unknown
+  pub author: String,
+  /// name of the attached file without `.epub`, unique among the books
+  pub file_stem: String,
+  pub indices: Vec<usize>,
+}
+
+impl BookOptions {
+  /// The books of `mode`, in the order of the articles.
+  pub fn groups(&self, articles: &[Article], mode: DeliveryMode, date: &str) -> Vec<Group> {
+    let mut groups = match mode {
+      DeliveryMode::Digest => vec![Group {
+        title: self.title(date),
+        author: self.author.clone(),
+        file_stem: file_stem(&self.title(date)).unwrap_or_else(|| format!("Pocket-{}", date)),
+        indices: (0..articles.len()).collect(),
+      }],
+      DeliveryMode::PerArticle => articles
+        .iter()
+        .enumerate()
+        .map(|(i, article)| Group {
+          title: if article.title.trim().is_empty() {
+            article.url.clone()
+          } else {
+            article.title.clone()
+          },
+          author: article
+            .byline
+            .clone()
+            .or_else(|| article.site_name.clone())
+            .unwrap_or_default(),
+          // articles may have the same title
+          file_stem: match file_stem(&article.title) {
+            Some(stem) => format!("{}-{}", stem, article.id),
+            None => format!("article-{}", article.id),
+          },
+          indices: vec![i],
+        })
+        .collect(),
+      DeliveryMode::PerTag => {
+        // an article with several tags is sent once, in the book of its first tag
+        let mut groups: Vec<Group> = vec![];
+        for (i, article) in articles.iter().enumerate() {
+          let title = match article.tags.first() {
+            Some(tag) => self.tag_title(date, tag),
+            None => self.title(date),
+          };
+          match groups.iter_mut().find(|g| g.title == title) {
+            Some(group) => group.indices.push(i),
+            None => groups.push(Group {
+              file_stem: file_stem(&title).unwrap_or_else(|| format!("Pocket-{}", date)),
+              title,
+              author: self.author.clone(),
+              indices: vec![i],
+            }),
+          }
+        }
+        groups
+      }
+    };
+
+    // e.g. tags differing only in punctuation
+    let mut stems: Vec<String> = vec![];
+    for group in groups.iter_mut() {
+      let stem = group.file_stem.clone();
+      let mut n = 1;
+      while stems.contains(&group.file_stem) {
+        n += 1;
+        group.file_stem = format!("{}-{}", stem, n);
+      }
+      stems.push(group.file_stem.clone());
+    }
+
+    for group in groups.iter_mut() {
+      self.sort_by_section(articles, &mut group.indices);
+    }
+    groups
+  }
+}
+
+/// `title` with the runs of other characters than letters and digits replaced
+/// with `-`, e.g. `Pocket 2026-10-18 (1/3)` -> `Pocket-2026-10-18-1-3`. `None`
+/// if no letter or digit is left.
+fn file_stem(title: &str) -> Option<String> {
+  let name: String = title
+    .chars()
+    .take(80)
+    .map(|c| if c.is_alphanumeric() { c } else { '-' })
+    .collect();
+  let name: Vec<&str> = name.split('-').filter(|part| !part.is_empty()).collect();
+  (!name.is_empty()).then(|| name.join("-"))
+}
+
+/// The excerpt of a book of an article, or the titles of the articles.
+pub fn description(articles: &[&Article]) -> String {
+  match articles {
+    [article] => article
+      .excerpt
+      .clone()
+      .unwrap_or_else(|| article.title.clone()),
+    _ => format!(
+      "{} articles: {}",
+      articles.len(),
+      articles
+        .iter()
+        .map(|a| a.title.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
+    ),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn file_stem_keeps_letters_and_digits() {
+    assert_eq!(
+      file_stem("Pocket 2026-10-18 (1/3)").as_deref(),
+      Some("Pocket-2026-10-18-1-3")
+    );
+    assert_eq!(file_stem("日本語の記事").as_deref(), Some("日本語の記事"));
+    assert_eq!(file_stem("?!… —"), None);
+  }
+}
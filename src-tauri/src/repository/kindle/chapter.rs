@@ -0,0 +1,113 @@
+use url::Url;
+
+use crate::domain::Article;
+use crate::library::html::escape;
+
+/// A chapter of the book holding `article`, whose images are already
+/// rewritten in `contents`. It ends with a link to the `next` article.
+pub fn chapter(article: &Article, contents: &str, next: Option<&Article>) -> String {
+  let mut body = format!("{}\n{}", header(article), contents);
+  if let Some(next) = next {
+    body.push_str(&format!(
+      "\n<nav class=\"next-article\">\n<p>Next: <a href=\"{}\">{}</a></p>\n</nav>",
+      file_name(next),
+      escape(&next.title)
+    ));
+  }
+
+  document(
+    &article.title,
+    article.lang.as_deref(),
+    article.dir.as_deref(),
+    &body,
+  )
+}
+
+/// The file of the chapter holding `article`. Pocket IDs are numbers, which
+/// cannot start an XML ID such as the one of the file in the manifest.
+pub fn file_name(article: &Article) -> String {
+  format!("article-{}.xhtml", &article.id)
+}
+
+/// An XHTML document of the book.
+pub fn document(title: &str, lang: Option<&str>, dir: Option<&str>, body: &str) -> String {
+  let mut html_attributes = String::new();
+  if let Some(lang) = lang.and_then(language_tag) {
+    html_attributes.push_str(&format!(r#" xml:lang="{0}" lang="{0}""#, lang));
+  }
+  if let Some(dir) = dir {
+    html_attributes.push_str(&format!(r#" dir="{}""#, escape(dir)));
+  }
+
+  format!(
+    r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops"{}>
+<head>
+<title>{}</title>
+<link rel="stylesheet" type="text/css" href="stylesheet.css" />
+</head>
+<body>
+{}
+</body>
+</html>"#,
+    html_attributes,
+    escape(title),
+    body
+  )
+}
+
+/// The title, byline, site with the original URL, and reading time.
+fn header(article: &Article) -> String {
+  let mut ret = format!(
+    "<header class=\"article-header\">\n<h1>{}</h1>\n",
+    escape(&article.title)
+  );
+
+  if let Some(byline) = &article.byline {
+    ret.push_str(&format!("<p class=\"byline\">{}</p>\n", escape(byline)));
+  }
+
+  let mut meta = vec![];
+  if let Some(site) = site_name(article) {
+    meta.push(format!(
+      r#"<a href="{}">{}</a>"#,
+      escape(&article.url),
+      escape(&site)
+    ));
+  }
+  if let Some(minutes) = article.reading_minutes() {
+    meta.push(format!("{} min read", minutes));
+  }
+  if !meta.is_empty() {
+    ret.push_str(&format!("<p class=\"meta\">{}</p>\n", meta.join(" · ")));
+  }
+
+  ret.push_str("</header>");
+  ret
+}
+
+/// The name of the site, or the host of the URL.
+pub fn site_name(article: &Article) -> Option<String> {
+  article.site_name.clone().or_else(|| host_name(article))
+}
+
+/// The host of the URL of `article`, without `www.`.
+pub fn host_name(article: &Article) -> Option<String> {
+  Url::parse(&article.url).ok().and_then(|url| {
+    url
+      .host_str()
+      .map(|host| host.trim_start_matches("www.").to_string())
+  })
+}
+
+/// `lang` if it looks like a BCP 47 language tag, normalized to use hyphens.
+pub fn language_tag(lang: &str) -> Option<String> {
+  let lang = lang.trim().replace('_', "-");
+  let valid = !lang.is_empty()
+    && lang.len() <= 35
+    && lang.split('-').all(|part| {
+      !part.is_empty() && part.len() <= 8 && part.chars().all(|c| c.is_ascii_alphanumeric())
+    });
+  valid.then_some(lang)
+}
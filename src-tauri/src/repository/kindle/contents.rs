@@ -0,0 +1,104 @@
+use crate::domain::Article;
+use crate::library::html::escape;
+
+use super::chapter::{document, file_name, site_name};
+
+/// the contents page of a book of several articles
+pub const CONTENTS_FILE: &str = "contents.xhtml";
+
+/// longest excerpt shown on the contents page, in characters
+const MAX_EXCERPT: usize = 280;
+
+/// Consecutive articles of a book under the same heading.
+pub struct Section<'a> {
+  /// `None` if the book has no sections
+  pub title: Option<&'a str>,
+  pub articles: Vec<&'a Article>,
+}
+
+/// The file of the first page of the `index`th section.
+pub fn section_file_name(index: usize) -> String {
+  format!("section-{}.xhtml", index + 1)
+}
+
+/// The contents page, listing the articles of each section with a summary.
+pub fn contents_page(sections: &[Section], lang: &str) -> String {
+  let mut body = String::from("<h1>Contents</h1>\n");
+
+  for (i, section) in sections.iter().enumerate() {
+    if let Some(title) = section.title {
+      body.push_str(&format!(
+        "<h2 class=\"contents-section\"><a href=\"{}\">{}</a></h2>\n",
+        section_file_name(i),
+        escape(title)
+      ));
+    }
+
+    body.push_str("<ul class=\"contents\">\n");
+    for article in &section.articles {
+      body.push_str(&format!(
+        "<li>\n<p class=\"contents-title\"><a href=\"{}\">{}</a></p>\n",
+        file_name(article),
+        escape(&article.title)
+      ));
+
+      let mut meta = vec![];
+      if let Some(byline) = &article.byline {
+        meta.push(escape(byline));
+      }
+      if section.title.is_none() {
+        if let Some(site) = site_name(article) {
+          meta.push(escape(&site));
+        }
+      }
+      if let Some(minutes) = article.reading_minutes() {
+        meta.push(format!("{} min read", minutes));
+      }
+      if !meta.is_empty() {
+        body.push_str(&format!(
+          "<p class=\"contents-meta\">{}</p>\n",
+          meta.join(" · ")
+        ));
+      }
+
+      if let Some(excerpt) = article.excerpt.as_deref().map(str::trim) {
+        if !excerpt.is_empty() {
+          body.push_str(&format!(
+            "<p class=\"contents-excerpt\">{}</p>\n",
+            escape(&truncate(excerpt, MAX_EXCERPT))
+          ));
+        }
+      }
+      body.push_str("</li>\n");
+    }
+    body.push_str("</ul>\n");
+  }
+
+  document("Contents", Some(lang), None, &body)
+}
+
+/// The first page of a section, listing its articles.
+pub fn section_page(section: &Section, lang: &str) -> String {
+  let title = section.title.unwrap_or_default();
+  let mut body = format!(
+    "<h1 class=\"section-title\">{}</h1>\n<ul class=\"section-contents\">\n",
+    escape(title)
+  );
+  for article in &section.articles {
+    body.push_str(&format!(
+      "<li><a href=\"{}\">{}</a></li>\n",
+      file_name(article),
+      escape(&article.title)
+    ));
+  }
+  body.push_str("</ul>");
+
+  document(title, Some(lang), None, &body)
+}
+
+fn truncate(text: &str, max: usize) -> String {
+  match text.char_indices().nth(max) {
+    Some((end, _)) => format!("{}…", text[..end].trim_end()),
+    None => text.into(),
+  }
+}
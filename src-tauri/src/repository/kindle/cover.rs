@@ -0,0 +1,243 @@
+use std::io::Cursor;
+
+use anyhow::{Context, Result};
+use image::{
+  imageops::{self, FilterType},
+  DynamicImage, ImageFormat,
+};
+use resvg::{tiny_skia, usvg};
+
+use super::optimize::{fonts, ImageOptions};
+use crate::domain::Image;
+use crate::library::html::escape;
+
+const FONT_FAMILY: &str =
+  "'DejaVu Serif', 'Noto Serif', 'Noto Serif CJK JP', 'Hiragino Mincho ProN', Georgia, serif";
+
+/// What is shown on the cover of a book.
+pub struct Cover<'a> {
+  pub title: &'a str,
+  /// the author of an article, or the number of articles
+  pub subtitle: &'a str,
+  pub date: &'a str,
+  /// titles of the articles, of which the first few are listed
+  pub articles: Vec<&'a str>,
+  /// an image of the first article, shown below the title
+  pub lead_image: Option<&'a Image>,
+}
+
+pub struct CoverOptions {
+  pub enabled: bool,
+  /// show an image of the first article
+  pub lead_image: bool,
+  /// number of article titles listed
+  pub max_articles: usize,
+}
+
+impl Default for CoverOptions {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      lead_image: true,
+      max_articles: 5,
+    }
+  }
+}
+
+/// Render the cover as a PNG of the device resolution.
+pub fn render_cover(
+  cover: &Cover,
+  options: &CoverOptions,
+  image: &ImageOptions,
+) -> Result<Vec<u8>> {
+  let width = image.max_width as f32;
+  let height = image.max_height as f32;
+  let margin = width * 0.08;
+  let text_width = width - margin * 2.0;
+
+  let mut svg = format!(
+    r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="{font}">
+<rect width="{w}" height="{h}" fill="white"/>
+"#,
+    w = width,
+    h = height,
+    font = FONT_FAMILY
+  );
+
+  // title and subtitle from the top
+  let mut y = height * 0.1;
+  let size = width * 0.065;
+  // bold glyphs are wider
+  for line in wrap(cover.title, text_width, size * 1.1, 4) {
+    y += size * 1.2;
+    svg.push_str(&text(margin, y, size, r#"font-weight="bold""#, &line));
+  }
+  for (size, line) in [(width * 0.04, cover.subtitle), (width * 0.035, cover.date)] {
+    if let Some(line) = wrap(line, text_width, size, 1).pop() {
+      y += size * 1.6;
+      svg.push_str(&text(margin, y, size, "", &line));
+    }
+  }
+  y += width * 0.04;
+  svg.push_str(&rule(margin, y, text_width, width * 0.006));
+
+  // article titles from the bottom
+  let size = width * 0.032;
+  let line_height = size * 1.6;
+  let listed = cover.articles.len().min(options.max_articles);
+  let more = cover.articles.len() - listed;
+  let lines = listed + if more > 0 { 1 } else { 0 };
+  let list_top = height - margin - line_height * lines as f32;
+  if lines > 0 {
+    svg.push_str(&rule(margin, list_top - size, text_width, width * 0.003));
+  }
+  let mut list_y = list_top;
+  for title in &cover.articles[..listed] {
+    if let Some(line) = wrap(title, text_width, size, 1).pop() {
+      list_y += line_height;
+      svg.push_str(&text(margin, list_y, size, "", &line));
+    }
+  }
+  if more > 0 {
+    list_y += line_height;
+    svg.push_str(&text(
+      margin,
+      list_y,
+      size,
+      r#"font-style="italic""#,
+      &format!("and {} more", more),
+    ));
+  }
+  svg.push_str("</svg>");
+
+  let opt = usvg::Options {
+    fontdb: fonts(),
+    ..Default::default()
+  };
+  let tree = usvg::Tree::from_str(&svg, &opt)?;
+  let mut pixmap =
+    tiny_skia::Pixmap::new(image.max_width, image.max_height).context("invalid cover size")?;
+  resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
+  let mut rendered =
+    image::load_from_memory_with_format(&pixmap.encode_png()?, ImageFormat::Png)?.to_rgba8();
+
+  // the lead image fills the space between the title and the list
+  let top = y + width * 0.05;
+  let bottom = if lines > 0 {
+    list_top - size * 2.0
+  } else {
+    height - margin
+  };
+  if let Some(lead) = cover.lead_image.filter(|_| options.lead_image) {
+    if bottom - top > height * 0.1 {
+      match image::load_from_memory(&lead.data) {
+        Ok(lead) => {
+          let lead = lead.resize(
+            text_width as u32,
+            (bottom - top) as u32,
+            FilterType::Triangle,
+          );
+          let x = margin + (text_width - lead.width() as f32) / 2.0;
+          let y = top + (bottom - top - lead.height() as f32) / 2.0;
+          imageops::overlay(&mut rendered, &lead.to_rgba8(), x as i64, y as i64);
+        }
+        Err(e) => log::warn!("failed to decode the lead image {}: {}", &lead.path, e),
+      }
+    }
+  }
+
+  let rendered = if image.grayscale {
+    DynamicImage::ImageLuma8(DynamicImage::ImageRgba8(rendered).to_luma8())
+  } else {
+    DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(rendered).to_rgb8())
+  };
+
+  let mut data = vec![];
+  rendered.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+  Ok(data)
+}
+
+fn text(x: f32, y: f32, size: f32, attributes: &str, line: &str) -> String {
+  format!(
+    "<text x=\"{}\" y=\"{}\" font-size=\"{}\" {}>{}</text>\n",
+    x,
+    y,
+    size,
+    attributes,
+    escape(line)
+  )
+}
+
+fn rule(x: f32, y: f32, width: f32, thickness: f32) -> String {
+  format!(
+    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"black\"/>\n",
+    x, y, width, thickness
+  )
+}
+
+/// Break `text` into at most `max_lines` lines fitting in `width`, with an
+/// ellipsis if it is cut. SVG has no line breaking, so the width of each
+/// character is estimated.
+fn wrap(text: &str, width: f32, size: f32, max_lines: usize) -> Vec<String> {
+  let mut lines: Vec<String> = vec![];
+  let mut line = String::new();
+  let mut line_width = 0.0;
+  // where the line can be broken without splitting a word
+  let mut last_space: Option<usize> = None;
+
+  for c in text
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .chars()
+  {
+    let w = char_width(c) * size;
+    if line_width + w > width && !line.is_empty() {
+      if lines.len() + 1 == max_lines {
+        let ellipsis = char_width('…') * size;
+        while line_width + ellipsis > width {
+          match line.pop() {
+            Some(c) => line_width -= char_width(c) * size,
+            None => break,
+          }
+        }
+        line.truncate(line.trim_end().len());
+        line.push('…');
+        lines.push(line);
+        return lines;
+      }
+      let rest = match last_space.filter(|_| c != ' ') {
+        Some(i) => line.split_off(i).trim_start().to_string(),
+        None => String::new(),
+      };
+      lines.push(line.trim_end().to_string());
+      line = rest;
+      line_width = line.chars().map(|c| char_width(c) * size).sum();
+      last_space = None;
+      if c == ' ' {
+        continue;
+      }
+    }
+    if c == ' ' {
+      last_space = Some(line.len());
+    }
+    line.push(c);
+    line_width += w;
+  }
+
+  if !line.is_empty() && lines.len() < max_lines {
+    lines.push(line);
+  }
+  lines
+}
+
+/// Rough advance of a character in ems.
+fn char_width(c: char) -> f32 {
+  match c as u32 {
+    // CJK, kana, hangul and full-width forms
+    0x1100..=0x115f | 0x2e80..=0xa4cf | 0xac00..=0xd7a3 | 0xf900..=0xfaff | 0xff00..=0xff60 => 1.0,
+    _ if c.is_ascii_uppercase() => 0.75,
+    _ if c == ' ' => 0.3,
+    _ => 0.6,
+  }
+}
@@ -0,0 +1,885 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::domain::repository::kindle::{
+  DeliveryError, KindleRepository, SendInput, SendOutput, VolumeOutput,
+};
+use crate::domain::{Article, ArticleId, DeliveryMode, Image};
+use crate::library::html::{combine_upright, escape, image_sources, outline, rewrite_images};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::Local;
+use epub_builder::EpubBuilder;
+use epub_builder::EpubContent;
+use epub_builder::EpubVersion;
+use epub_builder::ReferenceType;
+use epub_builder::TocElement;
+use epub_builder::ZipLibrary;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, MultiPart};
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use shaku::Component;
+
+use book::{description, Group};
+pub use book::{BookOptions, Sections};
+use chapter::{chapter, file_name as chapter_file_name, language_tag};
+use contents::{contents_page, section_file_name, section_page, Section, CONTENTS_FILE};
+pub use cover::CoverOptions;
+use cover::{render_cover, Cover};
+pub use optimize::ImageOptions;
+use optimize::{optimize_images, OptimizeOutput};
+use package::{add_publisher, rewrite_ncx, rewrite_opf, set_vertical, unescape_titles};
+use smtp::delivery_error;
+pub use smtp::{SmtpAuth, SmtpConfig, SmtpTls};
+pub use style::StyleOptions;
+use style::{embed_fonts, stylesheet};
+use volume::{encoded_size, pack, ATTACHMENT_OVERHEAD, CHAPTER_OVERHEAD, MESSAGE_OVERHEAD};
+
+mod book;
+mod chapter;
+mod contents;
+mod cover;
+mod optimize;
+mod package;
+mod smtp;
+mod style;
+mod volume;
+
+pub struct KindleRepositoryConfig {
+  // pub save_dir: PathBuf,
+  pub send_to: String,
+  pub send_from: String,
+  pub password: String,
+  pub image: ImageOptions,
+  pub smtp: SmtpConfig,
+  pub book: BookOptions,
+  pub cover: CoverOptions,
+  pub style: StyleOptions,
+  /// largest email accepted by Send to Kindle, after base64 encoding
+  pub max_message_size: usize,
+  /// most attachments accepted in an email by Send to Kindle
+  pub max_attachments: usize,
+}
+
+impl Default for KindleRepositoryConfig {
+  fn default() -> Self {
+    Self {
+      send_to: String::new(),
+      send_from: String::new(),
+      password: String::new(),
+      image: ImageOptions::default(),
+      smtp: SmtpConfig::default(),
+      book: BookOptions::default(),
+      cover: CoverOptions::default(),
+      style: StyleOptions::default(),
+      max_message_size: 50 * 1000 * 1000,
+      max_attachments: 25,
+    }
+  }
+}
+
+/// An article and its contents with the images rewritten.
+struct Chapter<'a> {
+  article: &'a Article,
+  contents: &'a str,
+  /// the Pocket cover of the article
+  lead_image: Option<&'a Image>,
+  section: Option<String>,
+}
+
+/// An EPUB to attach.
+struct Book {
+  title: String,
+  file_name: String,
+  ids: Vec<ArticleId>,
+  data: Vec<u8>,
+}
+
+#[derive(Component)]
+#[shaku(interface = KindleRepository)]
+pub struct KindleRepositoryImpl {
+  #[shaku(default = Arc::new(KindleRepositoryConfig::default()))]
+  config: Arc<KindleRepositoryConfig>,
+}
+
+#[async_trait]
+impl KindleRepository for KindleRepositoryImpl {
+  async fn send(&self, input: SendInput) -> Result<SendOutput, DeliveryError> {
+    log::info!("send {} articles", input.articles.len());
+
+    // decoding and encoding images takes a while
+    let repository = Self {
+      config: self.config.clone(),
+    };
+    let (optimized, books) = tokio::task::spawn_blocking(move || {
+      let optimized = optimize_images(&input.images, &repository.config.image);
+      let books =
+        repository.create_books(&input.articles, &optimized, &input.covers, input.mode)?;
+      anyhow::Ok((optimized, books))
+    })
+    .await
+    .context("failed to create the books")??;
+
+    let from = mailbox(&self.config.send_from)?;
+    let to = mailbox(&self.config.send_to)?;
+    let mailer = self
+      .config
+      .smtp
+      .transport(&self.config.send_from, &self.config.password)?;
+
+    // supported file types: .doc, .html, .txt, .pdf, .epub
+    let sizes: Vec<usize> = books
+      .iter()
+      .map(|book| encoded_size(book.data.len()) + ATTACHMENT_OVERHEAD)
+      .collect();
+    let limit = self
+      .config
+      .max_message_size
+      .saturating_sub(MESSAGE_OVERHEAD);
+
+    let mut volumes = vec![];
+    for range in pack(&sizes, limit, self.config.max_attachments) {
+      let books = &books[range.clone()];
+      let result = if sizes[range].iter().sum::<usize>() > limit {
+        Err(DeliveryError::TooLarge(anyhow!(
+          "{} exceeds {} bytes even on its own",
+          &books[0].title,
+          self.config.max_message_size
+        )))
+      } else {
+        self.deliver(&mailer, &from, &to, books).await
+      };
+
+      match &result {
+        Ok(_) => log::info!("Email sent successfully!"),
+        Err(e) => log::error!("failed to send {}: {}", &books[0].title, e),
+      }
+
+      // the volumes in an email share its result
+      for book in books {
+        volumes.push(VolumeOutput {
+          title: book.title.clone(),
+          ids: book.ids.clone(),
+          size: book.data.len(),
+          result: result.as_ref().map(|_| ()).map_err(duplicate),
+        });
+      }
+    }
+
+    Ok(SendOutput {
+      image_stats: optimized.stats,
+      volumes,
+    })
+  }
+}
+
+impl KindleRepositoryImpl {
+  async fn deliver(
+    &self,
+    mailer: &AsyncSmtpTransport<Tokio1Executor>,
+    from: &Mailbox,
+    to: &Mailbox,
+    books: &[Book],
+  ) -> Result<(), DeliveryError> {
+    let content_type = ContentType::parse("application/epub+zip").context("invalid MIME type")?;
+    let mut attachments = books.iter().map(|book| {
+      Attachment::new(book.file_name.clone()).body(book.data.clone(), content_type.clone())
+    });
+    let mut part = MultiPart::mixed().singlepart(attachments.next().context("no attachment")?);
+    for attachment in attachments {
+      part = part.singlepart(attachment);
+    }
+
+    let subject = books
+      .iter()
+      .map(|book| book.title.as_str())
+      .collect::<Vec<_>>()
+      .join(", ");
+    let email = Message::builder()
+      .from(from.clone())
+      .to(to.clone())
+      .subject(subject)
+      .multipart(part)
+      .context("failed to build the email")?;
+
+    let mut delay = self.config.smtp.backoff;
+    let mut attempt = 0;
+    loop {
+      match mailer.send(email.clone()).await {
+        Ok(_) => return Ok(()),
+        Err(e) => match delivery_error(e) {
+          DeliveryError::Transient(e) if attempt < self.config.smtp.retries => {
+            attempt += 1;
+            log::warn!("retrying in {:?} ({}): {}", delay, attempt, e);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+          }
+          e => return Err(e),
+        },
+      }
+    }
+  }
+
+  /// Build the books of `mode`, split into volumes that fit in an email each.
+  fn create_books(
+    &self,
+    articles: &[Article],
+    optimized: &OptimizeOutput,
+    covers: &HashMap<ArticleId, String>,
+    mode: DeliveryMode,
+  ) -> Result<Vec<Book>> {
+    // optimized images may have been renamed or dropped
+    let contents: Vec<String> = articles
+      .iter()
+      .map(|article| {
+        rewrite_images(&article.contents, |src| {
+          optimized
+            .paths
+            .get(src)
+            .cloned()
+            .unwrap_or_else(|| Some(src.into()))
+        })
+      })
+      .collect();
+
+    // text is compressed in the EPUB, so its raw size is an upper bound
+    let sizes: Vec<usize> = contents
+      .iter()
+      .map(|contents| {
+        let images: usize = image_sources(contents)
+          .iter()
+          .filter_map(|src| optimized.images.iter().find(|image| &image.path == src))
+          .map(|image| image.data.len())
+          .sum();
+        contents.len() + images + CHAPTER_OVERHEAD
+      })
+      .collect();
+
+    let date = Local::now().format("%Y-%m-%d").to_string();
+    let mut books = vec![];
+    for group in self.config.book.groups(articles, mode, &date) {
+      let chapters: Vec<Chapter> = group
+        .indices
+        .iter()
+        .map(|&i| Chapter {
+          article: &articles[i],
+          contents: contents[i].as_str(),
+          lead_image: covers
+            .get(&articles[i].id)
+            .and_then(|path| {
+              optimized
+                .paths
+                .get(path)
+                .cloned()
+                .unwrap_or(Some(path.clone()))
+            })
+            .and_then(|path| optimized.images.iter().find(|image| image.path == path)),
+          section: self.config.book.section(&articles[i]),
+        })
+        .collect();
+      let sizes: Vec<usize> = group.indices.iter().map(|&i| sizes[i]).collect();
+      books.extend(self.create_volumes(&group, &chapters, &sizes, optimized)?);
+    }
+
+    Ok(books)
+  }
+
+  /// Build a book, split into numbered volumes if it is too large.
+  fn create_volumes(
+    &self,
+    group: &Group,
+    chapters: &[Chapter],
+    sizes: &[usize],
+    optimized: &OptimizeOutput,
+  ) -> Result<Vec<Book>> {
+    let limit = self.volume_limit();
+    let mut pending: VecDeque<Range<usize>> = pack(sizes, limit, usize::MAX).into();
+    let mut volumes: Vec<(Range<usize>, Vec<u8>)> = vec![];
+
+    while let Some(range) = pending.pop_front() {
+      let data = self.create_epub(&group.title, group, &chapters[range.clone()], optimized)?;
+      if data.len() > limit && range.len() > 1 {
+        // the estimate was off, so split it in halves
+        let middle = range.start + range.len() / 2;
+        pending.push_front(middle..range.end);
+        pending.push_front(range.start..middle);
+        continue;
+      }
+      volumes.push((range, data));
+    }
+
+    let count = volumes.len();
+    let mut books = vec![];
+    for (i, (range, data)) in volumes.into_iter().enumerate() {
+      let (title, file_name, data) = if count == 1 {
+        (
+          group.title.clone(),
+          format!("{}.epub", &group.file_stem),
+          data,
+        )
+      } else {
+        // the title is in the EPUB, so build it again
+        let title = format!("{} ({}/{})", &group.title, i + 1, count);
+        let data = self.create_epub(&title, group, &chapters[range.clone()], optimized)?;
+        let file_name = format!("{}-{}-{}.epub", &group.file_stem, i + 1, count);
+        (title, file_name, data)
+      };
+      books.push(Book {
+        file_name,
+        title,
+        ids: chapters[range]
+          .iter()
+          .map(|chapter| chapter.article.id.clone())
+          .collect(),
+        data,
+      });
+    }
+
+    Ok(books)
+  }
+
+  fn create_cover(&self, title: &str, group: &Group, chapters: &[Chapter]) -> Result<Vec<u8>> {
+    let subtitle = match chapters {
+      [_] => group.author.clone(),
+      _ => format!("{} articles", chapters.len()),
+    };
+    let date = Local::now().format("%B %-d, %Y").to_string();
+    let cover = Cover {
+      title,
+      subtitle: &subtitle,
+      date: &date,
+      articles: match chapters {
+        [_] => vec![],
+        _ => chapters.iter().map(|c| c.article.title.as_str()).collect(),
+      },
+      lead_image: chapters.iter().find_map(|c| c.lead_image),
+    };
+    render_cover(&cover, &self.config.cover, &self.config.image)
+  }
+
+  /// Largest EPUB that fits in an email once encoded.
+  fn volume_limit(&self) -> usize {
+    let limit = self
+      .config
+      .max_message_size
+      .saturating_sub(MESSAGE_OVERHEAD + ATTACHMENT_OVERHEAD);
+    limit / 78 * 76 / 4 * 3
+  }
+
+  fn create_epub(
+    &self,
+    title: &str,
+    group: &Group,
+    chapters: &[Chapter],
+    optimized: &OptimizeOutput,
+  ) -> Result<Vec<u8>> {
+    let mut epub: Vec<u8> = vec![];
+
+    let mut builder =
+      EpubBuilder::new(ZipLibrary::new().map_err(epub_error)?).map_err(epub_error)?;
+
+    // `epub_builder` writes metadata and titles of the table of contents as
+    // is, with a random `urn:uuid` identifier and the time of the build as the
+    // date
+    let articles: Vec<&Article> = chapters.iter().map(|chapter| chapter.article).collect();
+    let lang = language(chapters);
+    let vertical = self.config.style.vertical_japanese
+      && chapters.iter().all(|chapter| is_japanese(chapter.article));
+    let builder = builder
+      .metadata("title", escape(title))
+      .map_err(epub_error)?
+      .metadata("lang", &lang)
+      .map_err(epub_error)?
+      .metadata("description", escape(&description(&articles)))
+      .map_err(epub_error)?
+      .epub_version(EpubVersion::V30);
+
+    if !group.author.is_empty() {
+      builder
+        .metadata("author", escape(&group.author))
+        .map_err(epub_error)?;
+    }
+
+    let mut tags: Vec<&str> = articles
+      .iter()
+      .flat_map(|a| a.tags.iter().map(String::as_str))
+      .collect();
+    tags.sort();
+    tags.dedup();
+    for tag in tags {
+      builder
+        .metadata("subject", escape(tag))
+        .map_err(epub_error)?;
+    }
+
+    // only the images of the articles in this volume
+    let used: HashSet<String> = chapters
+      .iter()
+      .flat_map(|chapter| image_sources(chapter.contents))
+      .collect();
+    for image in optimized.images.iter().filter(|i| used.contains(&i.path)) {
+      builder
+        .add_resource(&image.path, image.data.as_slice(), &image.mime)
+        .map_err(epub_error)?;
+    }
+
+    if self.config.cover.enabled {
+      let cover = self.create_cover(title, group, chapters)?;
+      builder
+        .add_cover_image("cover.png", cover.as_slice(), "image/png")
+        .map_err(epub_error)?;
+    }
+
+    // characters of the book, to subset the fonts to. ASCII is always kept
+    // for the navigation document and CSS generated content.
+    let mut text: BTreeSet<char> = (' '..='~').collect();
+
+    let sections = sections(chapters);
+    if chapters.len() > 1 {
+      let html = contents_page(&sections, &lang);
+      text.extend(html.chars());
+      builder
+        .add_content(
+          EpubContent::new(CONTENTS_FILE, html.as_bytes())
+            .title("Contents")
+            .reftype(ReferenceType::Toc),
+        )
+        .map_err(epub_error)?;
+    }
+
+    // articles are nested under their section in the table of contents
+    let nested = sections.len() > 1;
+    let mut start = 0;
+    for (i, section) in sections.iter().enumerate() {
+      if nested {
+        let html = section_page(section, &lang);
+        text.extend(html.chars());
+        builder
+          .add_content(
+            EpubContent::new(section_file_name(i), html.as_bytes())
+              .title(escape(section.title.unwrap_or_default())),
+          )
+          .map_err(epub_error)?;
+      }
+
+      for i in start..start + section.articles.len() {
+        let Chapter {
+          article, contents, ..
+        } = &chapters[i];
+        let next = chapters.get(i + 1).map(|c| c.article);
+        let file_name = chapter_file_name(article);
+        let level = if nested { 2 } else { 1 };
+        let (contents, headings) = outline(contents);
+        let contents = if vertical {
+          combine_upright(&contents)
+        } else {
+          contents
+        };
+        let html = chapter(article, &contents, next);
+        text.extend(html.chars());
+        let mut content = EpubContent::new(&file_name, html.as_bytes())
+          .title(escape(&article.title))
+          .level(level)
+          .reftype(ReferenceType::Text);
+        // `h3` are nested under the previous `h2`
+        for heading in headings {
+          content.toc.add(
+            TocElement::new(
+              format!("{}#{}", file_name, heading.id),
+              escape(&heading.title),
+            )
+            .level(level + heading.level - 1),
+          );
+        }
+        builder.add_content(content).map_err(epub_error)?;
+      }
+      start += section.articles.len();
+    }
+
+    // subsetting drops the alternate glyphs of punctuation for vertical
+    // writing, so vertical books embed whole fonts
+    let style = &self.config.style;
+    let subset = style.subset_fonts && !vertical;
+    let fonts = embed_fonts(&style.fonts, subset.then_some(&text));
+    for font in &fonts {
+      builder
+        .add_resource(&font.path, font.data.as_slice(), font.mime)
+        .map_err(epub_error)?;
+    }
+    builder
+      .stylesheet(stylesheet(style, &fonts, vertical).as_bytes())
+      .map_err(epub_error)?;
+
+    builder.generate(&mut epub).map_err(epub_error)?;
+    let epub = rewrite_ncx(&epub, unescape_titles)?;
+
+    let publisher = &self.config.book.publisher;
+    if publisher.is_empty() && !vertical {
+      return Ok(epub);
+    }
+    rewrite_opf(&epub, |mut opf| {
+      if !publisher.is_empty() {
+        opf = add_publisher(opf, publisher);
+      }
+      if vertical {
+        opf = set_vertical(opf);
+      }
+      opf
+    })
+  }
+}
+
+/// Runs of chapters of the same section. A book of a single section has no
+/// headings.
+fn sections<'a>(chapters: &'a [Chapter]) -> Vec<Section<'a>> {
+  let mut sections: Vec<Section> = vec![];
+  for chapter in chapters {
+    match sections.last_mut() {
+      Some(last) if last.title == chapter.section.as_deref() => last.articles.push(chapter.article),
+      _ => sections.push(Section {
+        title: chapter.section.as_deref(),
+        articles: vec![chapter.article],
+      }),
+    }
+  }
+  if let [section] = sections.as_mut_slice() {
+    section.title = None;
+  }
+  sections
+}
+
+/// The most common language of the articles, English if unknown.
+fn language(chapters: &[Chapter]) -> String {
+  let mut counts: Vec<(String, usize)> = vec![];
+  for lang in chapters
+    .iter()
+    .filter_map(|c| c.article.lang.as_deref().and_then(language_tag))
+  {
+    match counts.iter_mut().find(|(l, _)| *l == lang) {
+      Some((_, count)) => *count += 1,
+      None => counts.push((lang, 1)),
+    }
+  }
+  counts
+    .into_iter()
+    .max_by_key(|(_, count)| *count)
+    .map_or_else(|| "en".into(), |(lang, _)| lang)
+}
+
+fn is_japanese(article: &Article) -> bool {
+  article
+    .lang
+    .as_deref()
+    .and_then(language_tag)
+    .is_some_and(|lang| {
+      lang
+        .split('-')
+        .next()
+        .unwrap_or_default()
+        .eq_ignore_ascii_case("ja")
+    })
+}
+
+fn mailbox(address: &str) -> Result<Mailbox, DeliveryError> {
+  address
+    .parse()
+    .map_err(|e| DeliveryError::InvalidAddress(anyhow!("{:?}: {}", address, e)))
+}
+
+/// `epub_builder` errors are not `Sync`, so keep only the message.
+fn epub_error(e: epub_builder::Error) -> anyhow::Error {
+  anyhow!("failed to create the EPUB: {}", e)
+}
+
+/// The same error for another volume of a failed email.
+fn duplicate(e: &DeliveryError) -> DeliveryError {
+  match e {
+    DeliveryError::Auth(e) => DeliveryError::Auth(anyhow!("{:#}", e)),
+    DeliveryError::RejectedRecipient(e) => DeliveryError::RejectedRecipient(anyhow!("{:#}", e)),
+    DeliveryError::TooLarge(e) => DeliveryError::TooLarge(anyhow!("{:#}", e)),
+    DeliveryError::Transient(e) => DeliveryError::Transient(anyhow!("{:#}", e)),
+    DeliveryError::InvalidAddress(e) => DeliveryError::InvalidAddress(anyhow!("{:#}", e)),
+    DeliveryError::Other(e) => DeliveryError::Other(anyhow!("{:#}", e)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{io::Read, sync::Mutex, time::Duration};
+
+  use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+  };
+
+  use super::*;
+
+  /// What a local SMTP sink received.
+  #[derive(Default)]
+  struct Received {
+    commands: Vec<String>,
+    messages: Vec<String>,
+  }
+
+  /// Accept SMTP connections on a free local port, answering `RCPT TO` with
+  /// `rcpt_reply` and everything else with success.
+  async fn sink(rcpt_reply: &'static str) -> (u16, Arc<Mutex<Received>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let received = Arc::new(Mutex::new(Received::default()));
+
+    let state = received.clone();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        let received = state.clone();
+        tokio::spawn(async move {
+          let (reader, mut writer) = stream.into_split();
+          let mut lines = BufReader::new(reader).lines();
+          writer.write_all(b"220 sink\r\n").await.unwrap();
+
+          while let Ok(Some(line)) = lines.next_line().await {
+            received.lock().unwrap().commands.push(line.clone());
+            let command = line.to_ascii_uppercase();
+            let reply = if command.starts_with("RCPT") {
+              rcpt_reply
+            } else if command.starts_with("DATA") {
+              writer.write_all(b"354 go ahead\r\n").await.unwrap();
+              let mut message = String::new();
+              while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                  break;
+                }
+                message.push_str(&line);
+                message.push('\n');
+              }
+              received.lock().unwrap().messages.push(message);
+              "250 queued"
+            } else if command.starts_with("QUIT") {
+              writer.write_all(b"221 bye\r\n").await.unwrap();
+              break;
+            } else {
+              "250 ok"
+            };
+            writer
+              .write_all(format!("{}\r\n", reply).as_bytes())
+              .await
+              .unwrap();
+          }
+        });
+      }
+    });
+
+    (port, received)
+  }
+
+  fn repository(port: u16) -> KindleRepositoryImpl {
+    KindleRepositoryImpl {
+      config: Arc::new(KindleRepositoryConfig {
+        send_to: "reader@kindle.example".into(),
+        send_from: "me@example.com".into(),
+        smtp: SmtpConfig {
+          host: "127.0.0.1".into(),
+          port: Some(port),
+          tls: SmtpTls::None,
+          auth: SmtpAuth::None,
+          timeout: Duration::from_secs(5),
+          retries: 1,
+          backoff: Duration::from_millis(1),
+          ..Default::default()
+        },
+        cover: CoverOptions {
+          enabled: false,
+          ..Default::default()
+        },
+        ..Default::default()
+      }),
+    }
+  }
+
+  fn input(mode: DeliveryMode) -> SendInput {
+    let article = |id: &str, title: &str| Article {
+      id: id.into(),
+      title: title.into(),
+      url: format!("https://example.com/{}", id),
+      cover: None,
+      contents: format!("<p>{} body</p>", title),
+      byline: None,
+      site_name: None,
+      lang: Some("en".into()),
+      dir: None,
+      excerpt: None,
+      length: None,
+      tags: vec![],
+    };
+    SendInput {
+      articles: vec![article("1", "First"), article("2", "First")],
+      images: vec![],
+      covers: HashMap::new(),
+      mode,
+    }
+  }
+
+  /// Build the books of `articles` as a digest, without a cover.
+  fn build(config: KindleRepositoryConfig, articles: &[Article]) -> Vec<Book> {
+    let repository = KindleRepositoryImpl {
+      config: Arc::new(KindleRepositoryConfig {
+        cover: CoverOptions {
+          enabled: false,
+          ..Default::default()
+        },
+        ..config
+      }),
+    };
+    let optimized = optimize_images(&[], &repository.config.image);
+    repository
+      .create_books(articles, &optimized, &HashMap::new(), DeliveryMode::Digest)
+      .unwrap()
+  }
+
+  /// The file at `path` in `epub`.
+  fn read_file(epub: &[u8], path: &str) -> String {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(epub)).unwrap();
+    let mut contents = String::new();
+    archive
+      .by_name(path)
+      .unwrap()
+      .read_to_string(&mut contents)
+      .unwrap();
+    contents
+  }
+
+  #[test]
+  fn create_books_writes_the_publisher_and_escaped_titles() {
+    let mut articles = input(DeliveryMode::Digest).articles;
+    articles[0].title = "Q&A <live>".into();
+    let config = KindleRepositoryConfig {
+      book: BookOptions {
+        publisher: "Ink & Paper".into(),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+
+    let books = build(config, &articles);
+
+    assert_eq!(books.len(), 1);
+    let epub = &books[0].data;
+    let opf = read_file(epub, "OEBPS/content.opf");
+    assert!(opf.contains("<dc:publisher>Ink &amp; Paper</dc:publisher>"));
+    assert!(!opf.contains("page-progression-direction"));
+    let ncx = read_file(epub, "OEBPS/toc.ncx");
+    assert!(ncx.contains("<text>Q&amp;A &lt;live&gt;</text>"));
+    let nav = read_file(epub, "OEBPS/nav.xhtml");
+    assert!(nav.contains("Q&amp;A &lt;live&gt;"));
+  }
+
+  #[test]
+  fn create_books_writes_japanese_books_vertically() {
+    let mut articles = input(DeliveryMode::Digest).articles;
+    for article in articles.iter_mut() {
+      article.lang = Some("ja".into());
+    }
+    let config = || KindleRepositoryConfig {
+      style: StyleOptions {
+        vertical_japanese: true,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+
+    let opf = read_file(&build(config(), &articles)[0].data, "OEBPS/content.opf");
+    assert!(opf.contains("<spine page-progression-direction=\"rtl\" "));
+    assert!(opf.contains(r#"<meta name="primary-writing-mode" content="vertical-rl"/>"#));
+
+    // unless every article is in Japanese
+    articles[1].lang = Some("en".into());
+    let opf = read_file(&build(config(), &articles)[0].data, "OEBPS/content.opf");
+    assert!(!opf.contains("page-progression-direction"));
+    assert!(!opf.contains("primary-writing-mode"));
+  }
+
+  #[tokio::test]
+  async fn send_delivers_books_to_the_smtp_server() {
+    let (port, received) = sink("250 ok").await;
+
+    let ret = repository(port)
+      .send(input(DeliveryMode::PerArticle))
+      .await
+      .unwrap();
+
+    assert_eq!(ret.volumes.len(), 2);
+    assert!(ret.volumes.iter().all(|v| v.result.is_ok()));
+
+    let received = received.lock().unwrap();
+    assert!(received
+      .commands
+      .iter()
+      .any(|c| c == "RCPT TO:<reader@kindle.example>"));
+    // both books fit in one email, with names unique despite the same title
+    assert_eq!(received.messages.len(), 1);
+    let message = &received.messages[0];
+    assert!(message.contains("Subject: First, First"));
+    assert!(message.contains("application/epub+zip"));
+    assert!(message.contains("First-1.epub"));
+    assert!(message.contains("First-2.epub"));
+  }
+
+  #[tokio::test]
+  async fn send_reports_rejected_recipients() {
+    let (port, received) = sink("550 5.1.1 no such user").await;
+
+    let ret = repository(port)
+      .send(input(DeliveryMode::Digest))
+      .await
+      .unwrap();
+
+    assert_eq!(ret.volumes.len(), 1);
+    assert!(matches!(
+      ret.volumes[0].result,
+      Err(DeliveryError::RejectedRecipient(_))
+    ));
+    assert!(received.lock().unwrap().messages.is_empty());
+  }
+
+  #[tokio::test]
+  async fn send_classifies_rejections_by_enhanced_status() {
+    for (reply, expected) in [
+      ("550 5.7.1 sender not approved", "Auth"),
+      ("552 5.3.4 message too big", "TooLarge"),
+      ("550 mailbox unavailable", "Other"),
+    ] {
+      let (port, _) = sink(reply).await;
+
+      let ret = repository(port)
+        .send(input(DeliveryMode::Digest))
+        .await
+        .unwrap();
+
+      let error = ret.volumes[0].result.as_ref().unwrap_err();
+      assert!(format!("{:?}", error).starts_with(expected), "{}", reply);
+    }
+  }
+
+  #[tokio::test]
+  async fn send_retries_transient_failures() {
+    let (port, received) = sink("451 4.3.0 try again later").await;
+
+    let ret = repository(port)
+      .send(input(DeliveryMode::Digest))
+      .await
+      .unwrap();
+
+    assert!(matches!(
+      ret.volumes[0].result,
+      Err(DeliveryError::Transient(_))
+    ));
+    let received = received.lock().unwrap();
+    let attempts = received
+      .commands
+      .iter()
+      .filter(|c| c.starts_with("RCPT"))
+      .count();
+    assert_eq!(attempts, 2);
+  }
+}
@@ -0,0 +1,382 @@
+use std::{
+  collections::HashMap,
+  io::Cursor,
+  sync::{Arc, OnceLock},
+};
+
+use anyhow::{Context, Result};
+use image::{
+  codecs::jpeg::JpegEncoder,
+  imageops::{self, colorops::ColorMap, FilterType},
+  ColorType, DynamicImage, ImageFormat, Luma, Rgba, RgbaImage,
+};
+use resvg::{tiny_skia, usvg};
+
+use crate::domain::{Image, ImageStats};
+
+/// formats Kindle renders as they are
+const SUPPORTED_MIMES: &[&str] = &["image/jpeg", "image/png", "image/gif"];
+
+/// number of gray levels of an e-ink display
+const GRAY_LEVELS: u8 = 16;
+
+/// How images are prepared for e-ink displays.
+pub struct ImageOptions {
+  /// images are downsized to fit the device resolution
+  pub max_width: u32,
+  pub max_height: u32,
+  pub grayscale: bool,
+  /// dither grayscale images to the levels the display can show
+  pub dither: bool,
+  pub jpeg_quality: u8,
+}
+
+impl Default for ImageOptions {
+  fn default() -> Self {
+    // Kindle Paperwhite
+    Self {
+      max_width: 1072,
+      max_height: 1448,
+      grayscale: true,
+      dither: false,
+      jpeg_quality: 75,
+    }
+  }
+}
+
+pub struct OptimizeOutput {
+  pub images: Vec<Image>,
+  /// new path of each image, `None` if the image was dropped
+  pub paths: HashMap<String, Option<String>>,
+  pub stats: ImageStats,
+}
+
+pub fn optimize_images(images: &[Image], options: &ImageOptions) -> OptimizeOutput {
+  let mut ret = OptimizeOutput {
+    images: vec![],
+    paths: HashMap::new(),
+    stats: ImageStats::default(),
+  };
+
+  for image in images {
+    match optimize(image, options) {
+      Ok(optimized) => {
+        ret.stats.count += 1;
+        ret.stats.original_size += image.data.len();
+        ret.stats.optimized_size += optimized.data.len();
+        ret
+          .paths
+          .insert(image.path.clone(), Some(optimized.path.clone()));
+        ret.images.push(optimized);
+      }
+      Err(e) => {
+        // e.g. AVIF, as its decoder needs the native dav1d library, and
+        // Kindle cannot show it either
+        log::warn!("dropping image {} ({}): {}", &image.path, &image.mime, e);
+        ret.stats.dropped += 1;
+        ret.paths.insert(image.path.clone(), None);
+      }
+    }
+  }
+
+  log::info!(
+    "optimized {} images: {} -> {} bytes, {} dropped",
+    ret.stats.count,
+    ret.stats.original_size,
+    ret.stats.optimized_size,
+    ret.stats.dropped
+  );
+
+  ret
+}
+
+fn optimize(image: &Image, options: &ImageOptions) -> Result<Image> {
+  let decoded = match image.mime.as_str() {
+    "image/svg+xml" => rasterize(&image.data, options)?,
+    _ => image::load_from_memory(&image.data)?,
+  };
+
+  // graphics compress better without loss
+  let lossless = matches!(
+    image.mime.as_str(),
+    "image/png" | "image/gif" | "image/svg+xml"
+  );
+
+  let resized = decoded.width() > options.max_width || decoded.height() > options.max_height;
+  // whether the pixels change beyond the loss of re-encoding
+  let gray = matches!(decoded.color(), ColorType::L8 | ColorType::L16);
+  let transformed =
+    resized || decoded.color().has_alpha() || (options.grayscale && (!gray || options.dither));
+  let decoded = if resized {
+    decoded.resize(options.max_width, options.max_height, FilterType::Lanczos3)
+  } else {
+    decoded
+  };
+
+  // transparent areas show as black on the device otherwise
+  let decoded = flatten(&decoded);
+
+  let decoded = if options.grayscale {
+    let mut gray = decoded.to_luma8();
+    if options.dither {
+      imageops::dither(&mut gray, &GrayLevels(GRAY_LEVELS));
+    }
+    DynamicImage::ImageLuma8(gray)
+  } else {
+    DynamicImage::ImageRgb8(decoded.to_rgb8())
+  };
+
+  let mut data = vec![];
+  let (mime, extension) = if lossless {
+    decoded.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)?;
+    ("image/png", "png")
+  } else {
+    JpegEncoder::new_with_quality(&mut data, options.jpeg_quality).encode_image(&decoded)?;
+    ("image/jpeg", "jpg")
+  };
+
+  // keep the original when re-encoding does not pay off, unless it would
+  // undo the grayscale conversion, dithering or flattening
+  if !transformed
+    && SUPPORTED_MIMES.contains(&image.mime.as_str())
+    && data.len() >= image.data.len()
+  {
+    return Ok(image.clone());
+  }
+
+  let stem = image
+    .path
+    .rsplit_once('.')
+    .map(|(stem, _)| stem)
+    .unwrap_or(&image.path);
+
+  Ok(Image {
+    path: format!("{}.{}", stem, extension),
+    mime: mime.into(),
+    data,
+  })
+}
+
+/// System fonts, loaded once as it takes a while.
+pub fn fonts() -> Arc<usvg::fontdb::Database> {
+  static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+  FONTS
+    .get_or_init(|| {
+      let mut fonts = usvg::fontdb::Database::new();
+      fonts.load_system_fonts();
+      Arc::new(fonts)
+    })
+    .clone()
+}
+
+fn rasterize(data: &[u8], options: &ImageOptions) -> Result<DynamicImage> {
+  let opt = usvg::Options {
+    fontdb: fonts(),
+    ..Default::default()
+  };
+
+  let tree = usvg::Tree::from_data(data, &opt)?;
+  let size = tree.size();
+
+  // render small drawings above their nominal size to keep them sharp
+  let scale = (options.max_width as f32 / size.width())
+    .min(options.max_height as f32 / size.height())
+    .min(2.0);
+  let width = (size.width() * scale).ceil() as u32;
+  let height = (size.height() * scale).ceil() as u32;
+
+  let mut pixmap = tiny_skia::Pixmap::new(width, height).context("invalid svg size")?;
+  resvg::render(
+    &tree,
+    tiny_skia::Transform::from_scale(scale, scale),
+    &mut pixmap.as_mut(),
+  );
+
+  Ok(image::load_from_memory_with_format(
+    &pixmap.encode_png()?,
+    ImageFormat::Png,
+  )?)
+}
+
+/// Composite the image on a white background.
+fn flatten(image: &DynamicImage) -> DynamicImage {
+  if !image.color().has_alpha() {
+    return image.clone();
+  }
+  let mut background = RgbaImage::from_pixel(image.width(), image.height(), Rgba([255; 4]));
+  imageops::overlay(&mut background, &image.to_rgba8(), 0, 0);
+  DynamicImage::ImageRgba8(background)
+}
+
+/// Evenly spaced gray levels.
+struct GrayLevels(u8);
+
+impl GrayLevels {
+  fn step(&self) -> f32 {
+    255.0 / (self.0 - 1) as f32
+  }
+}
+
+impl ColorMap for GrayLevels {
+  type Color = Luma<u8>;
+
+  fn index_of(&self, color: &Luma<u8>) -> usize {
+    (color.0[0] as f32 / self.step()).round() as usize
+  }
+
+  fn map_color(&self, color: &mut Luma<u8>) {
+    color.0[0] = (self.index_of(color) as f32 * self.step()).round() as u8;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use image::{codecs::webp::WebPEncoder, GrayImage, RgbImage};
+
+  use super::*;
+
+  fn options(grayscale: bool, dither: bool) -> ImageOptions {
+    ImageOptions {
+      max_width: 100,
+      max_height: 100,
+      grayscale,
+      dither,
+      ..Default::default()
+    }
+  }
+
+  fn png(path: &str, image: DynamicImage) -> Image {
+    let mut data = vec![];
+    image
+      .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+      .unwrap();
+    Image {
+      path: path.into(),
+      mime: "image/png".into(),
+      data,
+    }
+  }
+
+  fn colors(width: u32, height: u32) -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+      image::Rgb([(x * 7) as u8, (y * 13) as u8, 128])
+    }))
+  }
+
+  fn decode(image: &Image) -> DynamicImage {
+    image::load_from_memory(&image.data).unwrap()
+  }
+
+  #[test]
+  fn optimize_images_fits_and_grays_images() {
+    let images = [png("images/0.png", colors(200, 100))];
+
+    let ret = optimize_images(&images, &options(true, false));
+
+    let image = &ret.images[0];
+    assert_eq!(image.path, "images/0.png");
+    assert_eq!(image.mime, "image/png");
+    let decoded = decode(image);
+    assert_eq!((decoded.width(), decoded.height()), (100, 50));
+    assert_eq!(decoded.color(), ColorType::L8);
+  }
+
+  #[test]
+  fn optimize_images_encodes_photos_as_jpeg() {
+    let mut data = vec![];
+    WebPEncoder::new_lossless(&mut data)
+      .encode(colors(20, 20).as_bytes(), 20, 20, ColorType::Rgb8.into())
+      .unwrap();
+    let images = [Image {
+      path: "images/0.webp".into(),
+      mime: "image/webp".into(),
+      data,
+    }];
+
+    let ret = optimize_images(&images, &options(false, false));
+
+    let image = &ret.images[0];
+    assert_eq!(image.path, "images/0.jpg");
+    assert_eq!(image.mime, "image/jpeg");
+    let decoded = decode(image);
+    assert_eq!((decoded.width(), decoded.height()), (20, 20));
+    assert_eq!(decoded.color(), ColorType::Rgb8);
+    assert_eq!(ret.paths["images/0.webp"].as_deref(), Some("images/0.jpg"));
+  }
+
+  #[test]
+  fn optimize_images_rasterizes_svg() {
+    let images = [Image {
+      path: "images/0.svg".into(),
+      mime: "image/svg+xml".into(),
+      data: br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+<rect width="20" height="10" fill="red"/></svg>"#
+        .to_vec(),
+    }];
+
+    let ret = optimize_images(&images, &options(true, false));
+
+    let image = &ret.images[0];
+    assert_eq!(image.path, "images/0.png");
+    assert_eq!(image.mime, "image/png");
+    // small drawings are rendered at twice their size
+    let decoded = decode(image);
+    assert_eq!((decoded.width(), decoded.height()), (40, 20));
+    assert_eq!(decoded.color(), ColorType::L8);
+  }
+
+  #[test]
+  fn optimize_images_dithers_to_the_gray_levels() {
+    let gradient = GrayImage::from_fn(64, 4, |x, _| Luma([(x * 4) as u8]));
+    let images = [png("images/0.png", DynamicImage::ImageLuma8(gradient))];
+
+    let ret = optimize_images(&images, &options(true, true));
+
+    let decoded = decode(&ret.images[0]).to_luma8();
+    assert!(decoded.pixels().all(|p| p.0[0] % 17 == 0));
+    assert!(decoded.pixels().any(|p| p.0[0] != 0 && p.0[0] != 255));
+  }
+
+  #[test]
+  fn optimize_images_keeps_originals_only_without_transforms() {
+    let gray = png(
+      "images/0.png",
+      DynamicImage::ImageLuma8(GrayImage::from_pixel(8, 8, Luma([128]))),
+    );
+    let color = png("images/1.png", colors(8, 8));
+    let images = [gray.clone(), color.clone()];
+
+    // nothing to change, and re-encoding does not make them smaller
+    let ret = optimize_images(&images, &options(false, false));
+    assert_eq!(ret.images[0].data, gray.data);
+    assert_eq!(ret.images[1].data, color.data);
+
+    // the color image has to be grayed even if it gets larger
+    let ret = optimize_images(&images, &options(true, false));
+    assert_eq!(ret.images[0].data, gray.data);
+    assert_ne!(ret.images[1].data, color.data);
+    assert_eq!(decode(&ret.images[1]).color(), ColorType::L8);
+  }
+
+  #[test]
+  fn optimize_images_drops_undecodable_images() {
+    let images = [
+      png("images/0.png", colors(8, 8)),
+      Image {
+        path: "images/1.avif".into(),
+        mime: "image/avif".into(),
+        data: b"\0\0\0\x1cftypavif\0\0\0\0avifmif1miaf".to_vec(),
+      },
+    ];
+
+    let ret = optimize_images(&images, &options(true, false));
+
+    assert_eq!(ret.images.len(), 1);
+    assert_eq!(ret.paths["images/1.avif"], None);
+    assert_eq!(ret.stats.count, 1);
+    assert_eq!(ret.stats.dropped, 1);
+    assert_eq!(ret.stats.original_size, images[0].data.len());
+    assert_eq!(ret.stats.optimized_size, ret.images[0].data.len());
+  }
+}
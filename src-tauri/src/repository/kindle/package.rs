@@ -0,0 +1,78 @@
+use std::io::{Cursor, Read, Write};
+
+use anyhow::Result;
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::library::html::escape;
+
+/// the package document written by `epub_builder`
+const OPF_PATH: &str = "OEBPS/content.opf";
+/// the EPUB 2 table of contents written by `epub_builder`
+const NCX_PATH: &str = "OEBPS/toc.ncx";
+
+/// Rewrite the package document of a generated EPUB with `f`, for what
+/// `epub_builder` has no option for.
+pub fn rewrite_opf<F: FnOnce(String) -> String>(epub: &[u8], f: F) -> Result<Vec<u8>> {
+  rewrite(epub, OPF_PATH, f)
+}
+
+/// Rewrite the `toc.ncx` of a generated EPUB with `f`.
+pub fn rewrite_ncx<F: FnOnce(String) -> String>(epub: &[u8], f: F) -> Result<Vec<u8>> {
+  rewrite(epub, NCX_PATH, f)
+}
+
+/// Rewrite the file at `path` with `f`. The other files are copied as is, so
+/// `mimetype` stays first and uncompressed.
+fn rewrite<F: FnOnce(String) -> String>(epub: &[u8], path: &str, f: F) -> Result<Vec<u8>> {
+  let mut archive = ZipArchive::new(Cursor::new(epub))?;
+  let mut writer = ZipWriter::new(Cursor::new(vec![]));
+  let mut f = Some(f);
+
+  for i in 0..archive.len() {
+    let mut file = archive.by_index(i)?;
+    if file.name() == path {
+      if let Some(f) = f.take() {
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        writer.start_file(path, FileOptions::default())?;
+        writer.write_all(f(contents).as_bytes())?;
+        continue;
+      }
+    }
+    writer.raw_copy_file(file)?;
+  }
+
+  Ok(writer.finish()?.into_inner())
+}
+
+/// `epub_builder` writes titles of the table of contents as is in the
+/// navigation document but escapes them in `toc.ncx`, so titles escaped for
+/// the former are escaped twice in the latter. Nothing else in `toc.ncx` has
+/// an `&`.
+pub fn unescape_titles(ncx: String) -> String {
+  ncx.replace("&amp;", "&")
+}
+
+/// Add `<dc:publisher>`, which `epub_builder` does not support.
+pub fn add_publisher(opf: String, publisher: &str) -> String {
+  opf.replacen(
+    "</metadata>",
+    &format!(
+      "  <dc:publisher>{}</dc:publisher>\n  </metadata>",
+      escape(publisher)
+    ),
+    1,
+  )
+}
+
+/// Turn pages from right to left, and tell Kindle the book is written
+/// vertically.
+pub fn set_vertical(opf: String) -> String {
+  opf
+    .replacen("<spine ", "<spine page-progression-direction=\"rtl\" ", 1)
+    .replacen(
+      "</metadata>",
+      "  <meta name=\"primary-writing-mode\" content=\"vertical-rl\"/>\n  </metadata>",
+      1,
+    )
+}
@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::time::Duration;
+
+use anyhow::Result;
+use lettre::{
+  transport::smtp::{
+    self,
+    authentication::{Credentials, Mechanism},
+  },
+  AsyncSmtpTransport, Tokio1Executor,
+};
+use serde::Deserialize;
+
+use crate::domain::repository::kindle::DeliveryError;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+  /// TLS from the start, usually on port 465
+  Implicit,
+  /// upgrade a plain connection, usually on port 587
+  StartTls,
+  /// plain text, only for a relay on the local network or a test sink
+  None,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpAuth {
+  /// send without logging in, e.g. through an internal relay
+  None,
+  Plain,
+  Login,
+  Xoauth2,
+}
+
+pub struct SmtpConfig {
+  pub host: String,
+  /// the default port of `tls` if not set
+  pub port: Option<u16>,
+  pub tls: SmtpTls,
+  pub auth: SmtpAuth,
+  /// the sender address if not set
+  pub username: Option<String>,
+  pub timeout: Duration,
+  /// how many times a transient failure is retried
+  pub retries: usize,
+  /// delay before the first retry, doubled for each next one
+  pub backoff: Duration,
+}
+
+impl Default for SmtpConfig {
+  fn default() -> Self {
+    Self {
+      host: "smtp.gmail.com".into(),
+      port: None,
+      tls: SmtpTls::Implicit,
+      auth: SmtpAuth::Plain,
+      username: None,
+      timeout: Duration::from_secs(60),
+      retries: 3,
+      backoff: Duration::from_secs(2),
+    }
+  }
+}
+
+impl SmtpConfig {
+  pub fn transport(
+    &self,
+    sender: &str,
+    password: &str,
+  ) -> Result<AsyncSmtpTransport<Tokio1Executor>> {
+    let mut builder = match self.tls {
+      SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?,
+      SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?,
+      SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host).port(25),
+    };
+    if let Some(port) = self.port {
+      builder = builder.port(port);
+    }
+
+    let mechanism = match self.auth {
+      SmtpAuth::None => None,
+      SmtpAuth::Plain => Some(Mechanism::Plain),
+      SmtpAuth::Login => Some(Mechanism::Login),
+      SmtpAuth::Xoauth2 => Some(Mechanism::Xoauth2),
+    };
+    if let Some(mechanism) = mechanism {
+      let username = self.username.as_deref().unwrap_or(sender);
+      builder = builder
+        .credentials(Credentials::new(username.into(), password.into()))
+        .authentication(vec![mechanism]);
+    }
+
+    Ok(builder.timeout(Some(self.timeout)).build())
+  }
+}
+
+/// Classify an SMTP error by the enhanced status code (RFC 3463) of its reply.
+pub fn delivery_error(e: smtp::Error) -> DeliveryError {
+  let transient = e.is_transient()
+    || e.is_timeout()
+    // connection and network errors have no other predicate
+    || !(e.is_permanent() || e.is_client() || e.is_response() || e.is_tls());
+
+  match enhanced_status(&e) {
+    Some((5, 1, _)) => DeliveryError::RejectedRecipient(e.into()),
+    Some((5, 7, _)) => DeliveryError::Auth(e.into()),
+    Some((5, 3, 4)) => DeliveryError::TooLarge(e.into()),
+    _ if transient => DeliveryError::Transient(e.into()),
+    _ => DeliveryError::Other(e.into()),
+  }
+}
+
+/// The class, subject and detail of the enhanced status code opening a reply.
+fn enhanced_status(e: &smtp::Error) -> Option<(u16, u16, u16)> {
+  // lettre keeps the text of a negative reply as the source of the error
+  let message = e.source()?.to_string();
+  let mut parts = message.split_whitespace().next()?.split('.');
+  let mut part = || parts.next()?.parse().ok();
+  let status = (part()?, part()?, part()?);
+  parts.next().is_none().then_some(status)
+}
@@ -0,0 +1,283 @@
+use std::{
+  collections::BTreeSet,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use allsorts::{
+  binary::read::ReadScope,
+  font_data::FontData,
+  subset::{subset, CmapTarget, SubsetProfile},
+};
+use anyhow::{Context, Result};
+use ttf_parser::{name_id, Face};
+
+/// the stylesheet shipped with the app, followed by the user's
+const DEFAULT_CSS: &str = include_str!("book.css");
+/// added for Japanese books written vertically
+const VERTICAL_CSS: &str = include_str!("vertical.css");
+
+/// formats of font files embedded as they are or subset
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf"];
+
+/// How the text of the books looks.
+pub struct StyleOptions {
+  /// CSS added after the default stylesheet
+  pub user_css: String,
+  /// TrueType or OpenType fonts embedded in every book
+  pub fonts: Vec<PathBuf>,
+  /// keep only the glyphs used by each book, as CJK fonts are several MB
+  pub subset_fonts: bool,
+  /// base font size in `em`, relative to the size set on the device
+  pub font_size: f32,
+  pub line_height: f32,
+  /// write books whose articles are all in Japanese vertically, with pages
+  /// turned from right to left
+  pub vertical_japanese: bool,
+}
+
+impl Default for StyleOptions {
+  fn default() -> Self {
+    Self {
+      user_css: String::new(),
+      fonts: vec![],
+      subset_fonts: true,
+      font_size: 1.0,
+      line_height: 1.5,
+      vertical_japanese: false,
+    }
+  }
+}
+
+impl StyleOptions {
+  /// The default options with `book.css` and the fonts in `fonts/` of `dir`,
+  /// if any.
+  pub fn load(dir: &Path) -> Result<Self> {
+    let mut options = Self::default();
+
+    let css = dir.join("book.css");
+    if css.exists() {
+      options.user_css =
+        fs::read_to_string(&css).with_context(|| format!("failed to read {}", css.display()))?;
+    }
+
+    let fonts = dir.join("fonts");
+    if fonts.is_dir() {
+      for entry in fs::read_dir(&fonts)? {
+        let path = entry?.path();
+        let extension = path
+          .extension()
+          .and_then(|e| e.to_str())
+          .map(str::to_ascii_lowercase);
+        if extension.is_some_and(|e| FONT_EXTENSIONS.contains(&e.as_str())) {
+          options.fonts.push(path);
+        }
+      }
+      // the family of the first font is preferred for the body
+      options.fonts.sort();
+    }
+
+    Ok(options)
+  }
+}
+
+/// A font file added to a book.
+pub struct Font {
+  /// path in the book
+  pub path: String,
+  pub mime: &'static str,
+  pub data: Vec<u8>,
+  family: String,
+  weight: u16,
+  italic: bool,
+}
+
+/// Load `fonts`, subset to the characters of `text` if any. Fonts which cannot
+/// be read are skipped.
+pub fn embed_fonts(fonts: &[PathBuf], text: Option<&BTreeSet<char>>) -> Vec<Font> {
+  let mut ret = vec![];
+  for path in fonts {
+    match load_font(path, ret.len(), text) {
+      Ok(font) => ret.push(font),
+      Err(e) => log::warn!("failed to embed the font {}: {:#}", path.display(), e),
+    }
+  }
+  ret
+}
+
+fn load_font(path: &Path, index: usize, text: Option<&BTreeSet<char>>) -> Result<Font> {
+  let data = fs::read(path)?;
+  let face = Face::parse(&data, 0)?;
+  let family = face
+    .names()
+    .into_iter()
+    .filter(|name| name.name_id == name_id::TYPOGRAPHIC_FAMILY)
+    .chain(
+      face
+        .names()
+        .into_iter()
+        .filter(|name| name.name_id == name_id::FAMILY),
+    )
+    .find_map(|name| name.to_string())
+    .context("the font has no family name")?;
+
+  let subset_data = match text {
+    Some(text) => {
+      // `.notdef` first, as required by `subset`
+      let mut glyphs: Vec<u16> = vec![0];
+      glyphs.extend(
+        text
+          .iter()
+          .filter_map(|c| face.glyph_index(*c))
+          .map(|g| g.0),
+      );
+      glyphs.sort_unstable();
+      glyphs.dedup();
+
+      let font = ReadScope::new(&data).read::<FontData>()?;
+      let provider = font.table_provider(0)?;
+      Some(subset(
+        &provider,
+        &glyphs,
+        &SubsetProfile::Minimal,
+        CmapTarget::Unicode,
+      )?)
+    }
+    None => None,
+  };
+
+  let otf = path
+    .extension()
+    .is_some_and(|e| e.eq_ignore_ascii_case("otf"));
+  Ok(Font {
+    path: format!(
+      "fonts/font-{}.{}",
+      index + 1,
+      if otf { "otf" } else { "ttf" }
+    ),
+    mime: if otf { "font/otf" } else { "font/ttf" },
+    weight: face.weight().to_number(),
+    italic: face.is_italic(),
+    family,
+    data: subset_data.unwrap_or(data),
+  })
+}
+
+/// The stylesheet of a book with `fonts`: the embedded fonts, the default
+/// stylesheet, the base font size and line height, then the user's CSS.
+pub fn stylesheet(options: &StyleOptions, fonts: &[Font], vertical: bool) -> String {
+  let mut css = String::new();
+
+  let mut families: Vec<String> = vec![];
+  for font in fonts {
+    let family = css_string(&font.family);
+    css.push_str(&format!(
+      "@font-face {{\n  font-family: {};\n  font-weight: {};\n  font-style: {};\n  src: url(\"{}\");\n}}\n\n",
+      family,
+      font.weight,
+      if font.italic { "italic" } else { "normal" },
+      font.path
+    ));
+    if !families.contains(&family) {
+      families.push(family);
+    }
+  }
+
+  css.push_str(DEFAULT_CSS);
+  if vertical {
+    css.push('\n');
+    css.push_str(VERTICAL_CSS);
+  }
+
+  css.push_str(&format!(
+    "\nbody {{\n  font-size: {}em;\n  line-height: {};\n",
+    options.font_size, options.line_height
+  ));
+  if !families.is_empty() {
+    css.push_str(&format!("  font-family: {}, serif;\n", families.join(", ")));
+  }
+  css.push_str("}\n");
+
+  if !options.user_css.is_empty() {
+    css.push('\n');
+    css.push_str(&options.user_css);
+  }
+  css
+}
+
+/// `value` quoted as a CSS string.
+fn css_string(value: &str) -> String {
+  let value: String = value
+    .chars()
+    .filter(|c| !matches!(c, '"' | '\\') && !c.is_control())
+    .collect();
+  format!("\"{}\"", value)
+}
+
+#[cfg(test)]
+mod tests {
+  use ttf_parser::GlyphId;
+
+  use super::*;
+
+  /// Tuffy marked as bold italic, see `testdata/README.md`
+  const FONT: &[u8] = include_bytes!("testdata/tuffy-bold-italic.ttf");
+
+  fn font_files(files: &[(&str, &[u8])]) -> (tempfile::TempDir, Vec<PathBuf>) {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = files
+      .iter()
+      .map(|(name, data)| {
+        let path = dir.path().join(name);
+        fs::write(&path, data).unwrap();
+        path
+      })
+      .collect();
+    (dir, paths)
+  }
+
+  #[test]
+  fn embed_fonts_subsets_to_the_used_characters() {
+    let (_dir, paths) = font_files(&[("Tuffy.ttf", FONT)]);
+    let text = BTreeSet::from(['A', 'b', '漢']);
+
+    let fonts = embed_fonts(&paths, Some(&text));
+
+    let data = &fonts[0].data;
+    assert!(data.len() < FONT.len());
+    let face = Face::parse(data, 0).unwrap();
+    assert_eq!(face.number_of_glyphs(), 3);
+    assert!(face.glyph_index('A').is_some_and(|g| g.0 != 0));
+    assert!(face.glyph_index('b').is_some_and(|g| g.0 != 0));
+    assert_eq!(face.glyph_index('c'), None);
+
+    // `.notdef` stays the first glyph
+    let original = Face::parse(FONT, 0).unwrap();
+    assert_eq!(
+      face.glyph_hor_advance(GlyphId(0)),
+      original.glyph_hor_advance(GlyphId(0))
+    );
+  }
+
+  #[test]
+  fn embed_fonts_reads_the_family_weight_and_style() {
+    let (_dir, paths) = font_files(&[("broken.ttf", b"not a font"), ("Tuffy.TTF", FONT)]);
+
+    let fonts = embed_fonts(&paths, None);
+
+    assert_eq!(fonts.len(), 1);
+    let font = &fonts[0];
+    assert_eq!(font.path, "fonts/font-1.ttf");
+    assert_eq!(font.mime, "font/ttf");
+    assert_eq!(font.data, FONT);
+    assert_eq!(font.family, "Tuffy");
+    assert_eq!(font.weight, 700);
+    assert!(font.italic);
+
+    let css = stylesheet(&StyleOptions::default(), &fonts, false);
+    assert!(css.starts_with(
+      "@font-face {\n  font-family: \"Tuffy\";\n  font-weight: 700;\n  font-style: italic;\n  src: url(\"fonts/font-1.ttf\");\n}\n"
+    ));
+    assert!(css.contains("  font-family: \"Tuffy\", serif;\n"));
+  }
+}
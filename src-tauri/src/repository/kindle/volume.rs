@@ -0,0 +1,72 @@
+use std::ops::Range;
+
+/// headers and MIME boundaries of a message
+pub const MESSAGE_OVERHEAD: usize = 16 * 1024;
+/// headers and MIME boundaries of each attachment
+pub const ATTACHMENT_OVERHEAD: usize = 1024;
+/// the chapter template and the header of each article
+pub const CHAPTER_OVERHEAD: usize = 2 * 1024;
+
+/// Size of an attachment of `size` bytes once base64-encoded in lines of 76
+/// characters.
+pub fn encoded_size(size: usize) -> usize {
+  let base64 = size.div_ceil(3) * 4;
+  base64 + base64 / 76 * 2
+}
+
+/// Split items of `sizes` into consecutive groups, each at most `limit` bytes
+/// and `max_count` items. An item larger than `limit` gets a group of its own.
+pub fn pack(sizes: &[usize], limit: usize, max_count: usize) -> Vec<Range<usize>> {
+  let mut ranges = vec![];
+  let mut start = 0;
+  let mut total = 0;
+
+  for (i, size) in sizes.iter().enumerate() {
+    if i > start && (total + size > limit || i - start >= max_count.max(1)) {
+      ranges.push(start..i);
+      start = i;
+      total = 0;
+    }
+    total += size;
+  }
+  if start < sizes.len() {
+    ranges.push(start..sizes.len());
+  }
+
+  ranges
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encoded_size_counts_padding_and_line_breaks() {
+    assert_eq!(encoded_size(0), 0);
+    assert_eq!(encoded_size(1), 4);
+    assert_eq!(encoded_size(3), 4);
+    assert_eq!(encoded_size(4), 8);
+    // 57 bytes make a full line of 76 characters
+    assert_eq!(encoded_size(57), 78);
+    assert_eq!(encoded_size(57 * 10), 780);
+  }
+
+  #[test]
+  fn pack_keeps_order_within_the_limit() {
+    assert_eq!(pack(&[4, 4, 4, 4], 10, usize::MAX), vec![0..2, 2..4]);
+    assert_eq!(pack(&[5, 5, 1], 10, usize::MAX), vec![0..2, 2..3]);
+    assert_eq!(pack(&[], 10, usize::MAX), Vec::<Range<usize>>::new());
+  }
+
+  #[test]
+  fn pack_isolates_items_over_the_limit() {
+    assert_eq!(pack(&[2, 20, 2], 10, usize::MAX), vec![0..1, 1..2, 2..3]);
+  }
+
+  #[test]
+  fn pack_limits_the_count() {
+    assert_eq!(pack(&[1; 5], 100, 2), vec![0..2, 2..4, 4..5]);
+    // no limit is taken as one item a group
+    assert_eq!(pack(&[1; 2], 100, 0), vec![0..1, 1..2]);
+  }
+}
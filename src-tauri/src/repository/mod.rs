@@ -1,3 +1,7 @@
+pub mod cache;
+pub mod credential;
+pub mod fetcher;
+pub mod image;
 pub mod kindle;
 pub mod pocket;
 pub mod readability;
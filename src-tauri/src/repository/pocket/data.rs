@@ -4,6 +4,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::domain::Article;
 
+use super::SENT_TAG;
+
 type ItemId = String;
 type ImageId = String;
 
@@ -73,6 +75,16 @@ pub(super) enum ActionType {
   tags_delete,
 }
 
+/// Tags set by the user, without the one marking sent articles.
+fn tags(tags: Option<&HashMap<String, Tag>>) -> Vec<String> {
+  let mut ret: Vec<String> = tags
+    .map(|tags| tags.values().map(|t| t.tag.clone()).collect())
+    .unwrap_or_default();
+  ret.retain(|tag| tag != SENT_TAG);
+  ret.sort();
+  ret
+}
+
 impl Into<Vec<Article>> for GetResponse {
   fn into(self) -> Vec<Article> {
     let mut ret = vec![];
@@ -81,12 +93,30 @@ impl Into<Vec<Article>> for GetResponse {
         id: v.item_id,
         title: v.given_title,
         url: v.given_url,
+        byline: v
+          .authors
+          .as_ref()
+          .and_then(|authors| authors.as_object())
+          .map(|authors| {
+            authors
+              .values()
+              .filter_map(|author| author["name"].as_str())
+              .collect::<Vec<_>>()
+              .join(", ")
+          })
+          .filter(|byline| !byline.is_empty()),
+        excerpt: Some(v.excerpt.clone()).filter(|excerpt| !excerpt.is_empty()),
         contents: v.excerpt,
         cover: v
           .images
           .as_ref()
           .and_then(|images| images.get("1"))
           .map(|image| image.src.clone()),
+        site_name: None,
+        lang: None,
+        dir: None,
+        length: None,
+        tags: tags(v.tags.as_ref()),
       })
     }
     log::info!("into: {:?}", &ret);
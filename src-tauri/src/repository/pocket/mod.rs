@@ -17,6 +17,7 @@ use tokio::fs::read_to_string;
 use crate::domain::repository::pocket::{
   ListOutput, MarkAsSentInput, PocketRepository, StartLoginOutput, WaitLoginOutput,
 };
+use crate::repository::fetcher::Fetcher;
 use data::*;
 
 mod data;
@@ -27,6 +28,9 @@ const POCKET_API_OAUTH_AUTHORIZE: &str = "https://getpocket.com/v3/oauth/authori
 const POCKET_API_GET: &str = "https://getpocket.com/v3/get";
 const POCKET_API_MODIFY: &str = "https://getpocket.com/v3/send";
 
+/// tag added to articles once sent
+const SENT_TAG: &str = "sent-to-kindle";
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PocketRepositoryState {
   pub access_token: String,
@@ -45,6 +49,7 @@ pub struct PocketRepositoryImpl {
   handle: Mutex<Option<JoinHandle<Result<String>>>>,
   access_token: Arc<Mutex<Option<String>>>,
   app: AppHandle,
+  fetcher: Arc<dyn Fetcher>,
 }
 
 #[async_trait]
@@ -86,7 +91,7 @@ impl PocketRepository for PocketRepositoryImpl {
 
   async fn start_login(&self) -> Result<StartLoginOutput> {
     log::info!("login");
-    let client = reqwest::Client::new();
+    let client = self.fetcher.client();
 
     let redirect_uri = "http://127.0.0.1:8080";
 
@@ -158,7 +163,7 @@ impl PocketRepository for PocketRepositoryImpl {
     self.wait_login().await?; // TODO: move
     let access_token = self.get_access_token()?;
 
-    let client = reqwest::Client::new();
+    let client = self.fetcher.client();
 
     let mut map = HashMap::new();
     map.insert("consumer_key", self.config.consumer_key.as_str());
@@ -190,7 +195,7 @@ impl PocketRepository for PocketRepositoryImpl {
       .into_iter()
       .map(|id| Action {
         action: ActionType::tags_add {
-          tags: "sent-to-kindle".into(),
+          tags: SENT_TAG.into(),
         },
         item_id: id,
       })
@@ -203,7 +208,7 @@ impl PocketRepository for PocketRepositoryImpl {
     map.insert("access_token", &access_token);
     map.insert("actions", &actions);
 
-    let client = reqwest::Client::new();
+    let client = self.fetcher.client();
 
     let res: serde_json::Value = client
       .post(POCKET_API_MODIFY)
@@ -221,12 +226,13 @@ impl PocketRepository for PocketRepositoryImpl {
 }
 
 impl PocketRepositoryImpl {
-  pub fn new(app: AppHandle, config: PocketRepositoryConfig) -> Self {
+  pub fn new(app: AppHandle, fetcher: Arc<dyn Fetcher>, config: PocketRepositoryConfig) -> Self {
     Self {
       access_token: Arc::new(Mutex::new(None)),
       config,
       handle: Mutex::new(None),
       app,
+      fetcher,
     }
   }
 
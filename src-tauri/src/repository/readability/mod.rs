@@ -0,0 +1,760 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use shaku::Component;
+use tauri::{AppHandle, Manager, Url, Window, WindowBuilder, WindowUrl};
+use tokio::{
+  sync::{mpsc, Semaphore},
+  time::{error::Elapsed, timeout},
+};
+
+use crate::domain::{
+  repository::{
+    cache::{CacheEntry, CacheKind, CacheRepository},
+    readability::{ConvertError, ConvertInput, ConvertOutput, ReadabilityRepository},
+  },
+  Article,
+};
+use crate::library::{charset::decode_html, html::absolutize, url::strip_tracking};
+use crate::repository::{
+  cache::{conditional_get, entry_of, revalidated, store},
+  fetcher::Fetcher,
+};
+use pages::{headings, next_page, normalize, remove_repeated};
+use requests::{PendingRequests, Request, Response};
+use rules::SiteRule;
+
+pub use rules::SiteRules;
+
+mod pages;
+mod requests;
+mod rules;
+
+const EVENT_REQUEST: &str = "readability-request";
+const EVENT_RESPONSE: &str = "readability-response";
+const EVENT_WORKER_READY: &str = "readability-worker-ready";
+
+const WORKER_URL: &str = "worker.html";
+const WORKER_LABEL_PREFIX: &str = "readability-worker";
+const RENDER_LABEL_PREFIX: &str = "readability-render";
+
+/// Render windows navigate to this host to pass the rendered page back to
+/// Rust. The navigations are cancelled, and `.invalid` never resolves anyway.
+const RENDER_HOST: &str = "readability-render.invalid";
+/// characters of the rendered page passed per navigation
+const RENDER_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Injected into render windows. Once the page and its scripts have loaded,
+/// tell Rust the page is ready to be captured.
+const RENDER_READY_SCRIPT: &str = r#"
+(function () {
+  if (window.top !== window.self) return;
+  window.addEventListener('load', function () {
+    setTimeout(function () {
+      location.href = 'https://__HOST__/__NONCE__/ready';
+    }, __SETTLE_MS__);
+  });
+})();
+"#;
+
+/// Evaluated in a render window once ready. Passes the first chunk of the
+/// rendered page, then Rust asks for each next one.
+const RENDER_CAPTURE_SCRIPT: &str = r#"
+(function () {
+  var url = location.href;
+  var html = document.documentElement.outerHTML;
+  var chunks = [];
+  var start = 0;
+  while (start < html.length) {
+    var end = Math.min(start + __CHUNK_SIZE__, html.length);
+    // encodeURIComponent rejects a surrogate pair split in two
+    var code = html.charCodeAt(end - 1);
+    if (end < html.length && code >= 0xd800 && code <= 0xdbff) end--;
+    chunks.push(html.slice(start, end));
+    start = end;
+  }
+  if (chunks.length === 0) chunks.push('');
+
+  window.__readabilitySend__NONCE__ = function (i) {
+    location.href = 'https://__HOST__/__NONCE__/' + i + '/' + chunks.length +
+      '?url=' + encodeURIComponent(url) + '&data=' + encodeURIComponent(chunks[i]);
+  };
+  window.__readabilitySend__NONCE__(0);
+})();
+"#;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ReadabilityOutput {
+  pub title: String,
+  pub content: String,
+  pub textContent: String,
+  pub length: usize,
+  pub excerpt: String,
+  pub byline: Option<String>,
+  pub dir: Option<String>,
+  pub siteName: Option<String>,
+  pub lang: Option<String>,
+}
+
+/// A fetched article page.
+#[derive(Deserialize, Debug)]
+struct Page {
+  /// the URL after redirects
+  pub url: String,
+  pub content: String,
+}
+
+struct Fetched {
+  page: Page,
+  /// whether the page differs from the cached one
+  changed: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct WorkerReady {
+  pub label: String,
+}
+
+pub struct ReadabilityRepositoryConfig {
+  /// how long to wait for an article page to be downloaded or rendered
+  pub fetch_timeout: Duration,
+  /// how long to wait for `Readability.js` before giving up on a request
+  pub extract_timeout: Duration,
+  /// maximum number of articles converted at the same time
+  pub concurrency: usize,
+  /// maximum number of pages fetched from the same host at the same time
+  pub concurrency_per_domain: usize,
+  /// load the article in a hidden window so that JavaScript-rendered pages are
+  /// extracted after their scripts run
+  pub render_javascript: bool,
+  /// how long to wait after the `load` event before capturing a rendered page
+  pub render_settle: Duration,
+  pub rules: SiteRules,
+  /// maximum number of pages followed for an article split into pages
+  pub max_pages: usize,
+  /// number of hidden windows hosting `Readability.js`, see `spawn_workers`
+  pub workers: usize,
+}
+
+impl Default for ReadabilityRepositoryConfig {
+  fn default() -> Self {
+    Self {
+      fetch_timeout: Duration::from_secs(30),
+      extract_timeout: Duration::from_secs(30),
+      concurrency: 8,
+      concurrency_per_domain: 2,
+      render_javascript: false,
+      render_settle: Duration::from_secs(1),
+      rules: SiteRules::default(),
+      max_pages: 10,
+      workers: 2,
+    }
+  }
+}
+
+/// Hidden windows hosting `Readability.js`, see `worker.html`.
+pub struct WorkerPool {
+  idle: Mutex<Vec<String>>,
+  ready: Mutex<HashSet<String>>,
+  available: Semaphore,
+}
+
+impl Default for WorkerPool {
+  fn default() -> Self {
+    Self {
+      idle: Mutex::new(vec![]),
+      ready: Mutex::new(HashSet::new()),
+      // workers join once they report ready
+      available: Semaphore::new(0),
+    }
+  }
+}
+
+impl WorkerPool {
+  /// called whenever a worker window has (re)loaded and listens for requests
+  fn add(&self, label: String) {
+    if self.ready.lock().unwrap().insert(label.clone()) {
+      log::info!("worker ready: {}", &label);
+      self.release(label);
+    }
+  }
+
+  async fn acquire(&self) -> Result<WorkerGuard<'_>> {
+    self.available.acquire().await?.forget();
+    let label = self.idle.lock().unwrap().pop().context("no idle worker")?;
+    Ok(WorkerGuard {
+      pool: self,
+      label: Some(label),
+    })
+  }
+
+  fn release(&self, label: String) {
+    self.idle.lock().unwrap().push(label);
+    self.available.add_permits(1);
+  }
+}
+
+/// Returns the worker to the pool when the request finishes or is cancelled.
+struct WorkerGuard<'a> {
+  pool: &'a WorkerPool,
+  label: Option<String>,
+}
+
+impl WorkerGuard<'_> {
+  fn label(&self) -> &str {
+    self.label.as_deref().unwrap()
+  }
+
+  /// Drop the worker instead of returning it. It rejoins the pool once it
+  /// reports ready again.
+  fn discard(mut self) -> String {
+    let label = self.label.take().unwrap();
+    self.pool.ready.lock().unwrap().remove(&label);
+    label
+  }
+}
+
+impl Drop for WorkerGuard<'_> {
+  fn drop(&mut self) {
+    if let Some(label) = self.label.take() {
+      self.pool.release(label);
+    }
+  }
+}
+
+#[derive(Component)]
+#[shaku(interface = ReadabilityRepository)]
+pub struct ReadabilityRepositoryImpl {
+  config: ReadabilityRepositoryConfig,
+  app: AppHandle,
+  fetcher: Arc<dyn Fetcher>,
+  cache: Arc<dyn CacheRepository>,
+  pending: Arc<PendingRequests>,
+  pool: Arc<WorkerPool>,
+  permits: Semaphore,
+  domain_permits: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+#[async_trait]
+impl ReadabilityRepository for ReadabilityRepositoryImpl {
+  async fn convert(&self, input: ConvertInput) -> Result<ConvertOutput, ConvertError> {
+    let _permit = self
+      .permits
+      .acquire()
+      .await
+      .map_err(|e| ConvertError::Fetch(e.into()))?;
+
+    let mut fetched = self
+      .fetch(&input.article.url)
+      .await
+      .map_err(|e| stage_error(e, ConvertError::Fetch))?;
+    let mut url = Url::parse(&fetched.page.url).map_err(|e| ConvertError::Fetch(e.into()))?;
+
+    let rule = self.config.rules.find(&url);
+    if let Some(link) = rule.and_then(|r| r.single_page_link(&fetched.page.content, &url)) {
+      log::info!("following the single page link {}", link);
+      match self.fetch(link.as_str()).await {
+        Ok(single) => {
+          fetched = single;
+          url = Url::parse(&fetched.page.url).map_err(|e| ConvertError::Fetch(e.into()))?;
+        }
+        Err(e) => log::warn!("failed to fetch the single page {}: {:#}", link, e),
+      }
+    }
+
+    let next = next_page(&fetched.page.content, &url, next_page_selectors(rule));
+    let mut ret = self.extract_page(&input.article.url, fetched, rule).await?;
+    ret.content = absolutize(&ret.content, &url);
+
+    self.append_pages(&mut ret, &url, next, rule).await;
+
+    Ok(ConvertOutput {
+      article: Article {
+        id: input.article.id,
+        // the title of a site rule wins over the one saved in Pocket
+        title: match rule {
+          Some(rule) if !rule.title.is_empty() && !ret.title.is_empty() => ret.title,
+          _ => input.article.title,
+        },
+        url: strip_tracking(url.clone()).into(),
+        cover: input.article.cover,
+        // contents: ret.textContent,
+        contents: ret.content,
+        byline: non_empty(ret.byline).or(input.article.byline),
+        site_name: non_empty(ret.siteName),
+        lang: non_empty(ret.lang),
+        dir: ret
+          .dir
+          .filter(|dir| matches!(dir.as_str(), "ltr" | "rtl" | "auto")),
+        excerpt: non_empty(Some(ret.excerpt)).or(input.article.excerpt),
+        length: Some(ret.length),
+        tags: input.article.tags,
+      },
+    })
+  }
+}
+
+impl ReadabilityRepositoryImpl {
+  /// Extract a page with the site rule, falling back to `Readability.js`.
+  async fn extract_page(
+    &self,
+    key: &str,
+    mut fetched: Fetched,
+    rule: Option<&SiteRule>,
+  ) -> Result<ReadabilityOutput, ConvertError> {
+    let rule = match rule {
+      Some(rule) => rule,
+      None => return self.extract(key, fetched).await,
+    };
+
+    let original = fetched.page.content;
+    fetched.page.content = rule.strip(&original);
+    let mut ret = match rule.extract(&fetched.page.content) {
+      Some(ret) => ret,
+      None => self.extract(key, fetched).await?,
+    };
+    rule.apply(&original, &mut ret);
+    Ok(ret)
+  }
+
+  /// Follow the next page links from `next`, appending each page to `ret`.
+  /// A page that fails ends the article there.
+  async fn append_pages(
+    &self,
+    ret: &mut ReadabilityOutput,
+    url: &Url,
+    mut next: Option<Url>,
+    rule: Option<&SiteRule>,
+  ) {
+    let mut visited = vec![url.clone()];
+    let mut known = headings(&ret.content);
+    known.insert(normalize(&ret.title));
+
+    while let Some(page_url) = next.take() {
+      if visited.len() >= self.config.max_pages.max(1) || visited.contains(&page_url) {
+        break;
+      }
+      visited.push(page_url.clone());
+      log::info!("following the next page {}", page_url);
+
+      let fetched = match self.fetch(page_url.as_str()).await {
+        Ok(fetched) => fetched,
+        Err(e) => {
+          log::warn!("failed to fetch the next page {}: {:#}", page_url, e);
+          break;
+        }
+      };
+      let final_url = Url::parse(&fetched.page.url).unwrap_or_else(|_| page_url.clone());
+      next = next_page(&fetched.page.content, &final_url, next_page_selectors(rule));
+
+      let page = match self.extract_page(page_url.as_str(), fetched, rule).await {
+        Ok(page) => page,
+        Err(e) => {
+          log::warn!("failed to extract the next page {}: {:#}", page_url, e);
+          break;
+        }
+      };
+
+      let content = remove_repeated(&page.content, &known);
+      known.extend(headings(&page.content));
+
+      ret.content.push_str(&absolutize(&content, &final_url));
+      ret.textContent.push('\n');
+      ret.textContent.push_str(&page.textContent);
+      ret.length += page.length;
+    }
+  }
+
+  /// Call `Readability.js` from Rust, unless the page is the one extracted
+  /// last time.
+  async fn extract(&self, key: &str, fetched: Fetched) -> Result<ReadabilityOutput, ConvertError> {
+    if let Some(ret) = self.extracted(key, fetched.changed).await {
+      return Ok(ret);
+    }
+
+    let url = fetched.page.url.clone();
+    let ret = self
+      .parse(fetched.page)
+      .await
+      .map_err(|e| stage_error(e, ConvertError::Extract))?;
+
+    match serde_json::to_vec(&ret) {
+      Ok(data) => {
+        store(
+          self.cache.as_ref(),
+          CacheKind::Extracted,
+          key,
+          &CacheEntry {
+            url,
+            content_type: Some("application/json".into()),
+            data,
+            ..Default::default()
+          },
+        )
+        .await
+      }
+      Err(e) => log::warn!("failed to serialize the extraction of {}: {}", url, e),
+    }
+
+    Ok(ret)
+  }
+
+  pub fn new(
+    app: AppHandle,
+    fetcher: Arc<dyn Fetcher>,
+    cache: Arc<dyn CacheRepository>,
+    config: ReadabilityRepositoryConfig,
+  ) -> Self {
+    let pending = Arc::new(PendingRequests::default());
+    let pool = Arc::new(WorkerPool::default());
+
+    // set callbacks
+    let pending_ref = pending.clone();
+    app.listen_global(EVENT_RESPONSE, move |event| {
+      if let Err(e) = pending_ref.resolve(event.payload()) {
+        log::error!("invalid readability response: {}", e);
+      }
+    });
+
+    let pool_ref = pool.clone();
+    app.listen_global(EVENT_WORKER_READY, move |event| {
+      match event.payload().map(serde_json::from_str::<WorkerReady>) {
+        Some(Ok(ready)) => pool_ref.add(ready.label),
+        _ => log::error!("invalid worker ready event: {:?}", event.payload()),
+      }
+    });
+
+    Self {
+      permits: Semaphore::new(config.concurrency.max(1)),
+      domain_permits: Mutex::new(HashMap::new()),
+      config,
+      app,
+      fetcher,
+      cache,
+      pending,
+      pool,
+    }
+  }
+
+  async fn parse(&self, page: Page) -> Result<ReadabilityOutput> {
+    let worker = timeout(self.config.extract_timeout, self.pool.acquire())
+      .await
+      .context("no readability worker available")??;
+    let window = self
+      .app
+      .get_window(worker.label())
+      .context("worker window not found")?;
+
+    let (id, rx) = self.pending.start();
+
+    // -> send event
+    let request = Request {
+      id,
+      url: page.url,
+      content: page.content,
+    };
+    if let Err(e) = window.emit(EVENT_REQUEST, request) {
+      self.pending.cancel(id);
+      return Err(e.into());
+    }
+
+    log::info!("waiting for response {} from {}...", id, worker.label());
+
+    // <- receive event
+    let response: Response = match self.pending.wait(id, rx, self.config.extract_timeout).await {
+      Ok(response) => response,
+      Err(e) => {
+        // the worker may be stuck, so reload it before it takes more work
+        worker.discard();
+        window.eval("location.reload()")?;
+        return Err(e);
+      }
+    };
+
+    response.article()
+  }
+
+  /// Fetch the page, reusing the cached one while fresh, when the server
+  /// reports it unchanged, or when the server can't be reached.
+  async fn fetch(&self, url: &str) -> Result<Fetched> {
+    let cached = self.cache.get(CacheKind::Page, url).await;
+    if let Some(cached) = cached.as_ref().filter(|cached| cached.fresh) {
+      log::debug!("using cached {}", url);
+      return Ok(Fetched {
+        page: page_of(cached),
+        changed: false,
+      });
+    }
+
+    let permits = self.domain_permits(url)?;
+    let _permit = permits.acquire().await?;
+
+    let downloaded = timeout(
+      self.config.fetch_timeout,
+      self.download(url, cached.as_ref()),
+    )
+    .await
+    .with_context(|| format!("fetching {} timed out", url))
+    .and_then(|ret| ret);
+
+    let ret = revalidated(
+      self.cache.as_ref(),
+      CacheKind::Page,
+      url,
+      downloaded,
+      cached,
+    )
+    .await?;
+    Ok(Fetched {
+      page: page_of(&ret.entry),
+      changed: ret.changed,
+    })
+  }
+
+  /// Download the page, or `None` if `cached` is still valid.
+  async fn download(&self, url: &str, cached: Option<&CacheEntry>) -> Result<Option<CacheEntry>> {
+    if self.config.render_javascript {
+      let page = self.render(url).await?;
+      return Ok(Some(CacheEntry {
+        url: page.url,
+        content_type: Some("text/html; charset=utf-8".into()),
+        data: page.content.into_bytes(),
+        ..Default::default()
+      }));
+    }
+
+    let Some(res) = conditional_get(self.fetcher.get(url), cached).await? else {
+      return Ok(None);
+    };
+
+    let mut entry = entry_of(&res);
+    entry.data = res.bytes().await?.to_vec();
+    Ok(Some(entry))
+  }
+
+  /// The cached extraction result, if the page hasn't changed since.
+  async fn extracted(&self, url: &str, changed: bool) -> Option<ReadabilityOutput> {
+    if changed {
+      return None;
+    }
+    let entry = self.cache.get(CacheKind::Extracted, url).await?;
+    serde_json::from_slice(&entry.data).ok()
+  }
+
+  fn domain_permits(&self, url: &str) -> Result<Arc<Semaphore>> {
+    let host = Url::parse(url)?.host_str().unwrap_or_default().to_string();
+    let mut domain_permits = self.domain_permits.lock().unwrap();
+    Ok(
+      domain_permits
+        .entry(host)
+        .or_insert_with(|| Arc::new(Semaphore::new(self.config.concurrency_per_domain.max(1))))
+        .clone(),
+    )
+  }
+
+  /// Load `url` in a hidden window and capture the DOM after scripts ran.
+  ///
+  /// The page has no access to the IPC. It passes the DOM back by navigating
+  /// to `RENDER_HOST`, which the window cancels, in chunks acknowledged by
+  /// `eval`. The URLs carry a random nonce so that frames of other origins
+  /// cannot pass content.
+  async fn render(&self, url: &str) -> Result<Page> {
+    let url = Url::parse(url)?;
+    let id = self.pending.next_id();
+    let label = format!("{}-{}", RENDER_LABEL_PREFIX, id);
+    let nonce: String = rand::thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(32)
+      .map(char::from)
+      .collect();
+
+    let script = RENDER_READY_SCRIPT
+      .replace("__HOST__", RENDER_HOST)
+      .replace("__NONCE__", &nonce)
+      .replace(
+        "__SETTLE_MS__",
+        &self.config.render_settle.as_millis().to_string(),
+      );
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let handler_nonce = nonce.clone();
+    let window = WindowBuilder::new(&self.app, &label, WindowUrl::External(url))
+      .visible(false)
+      .initialization_script(&script)
+      .on_navigation(move |url| {
+        if url.host_str() != Some(RENDER_HOST) {
+          return true;
+        }
+        match render_message(&url, &handler_nonce) {
+          Some(message) => {
+            let _ = tx.send(message);
+          }
+          None => log::warn!("ignored a render message without the nonce"),
+        }
+        false
+      })
+      .build()?;
+    let guard = RenderGuard { window };
+
+    log::info!("waiting for render {}...", id);
+
+    let capture = RENDER_CAPTURE_SCRIPT
+      .replace("__HOST__", RENDER_HOST)
+      .replace("__NONCE__", &nonce)
+      .replace("__CHUNK_SIZE__", &RENDER_CHUNK_SIZE.to_string());
+    let mut content = String::new();
+    let mut received = 0;
+
+    // `fetch` times out the render
+    loop {
+      match rx.recv().await.context("the render window went away")? {
+        // the page (re)loaded, such as after a redirect by a script
+        RenderMessage::Ready => {
+          content.clear();
+          received = 0;
+          guard.window.eval(&capture)?;
+        }
+        RenderMessage::Chunk {
+          index,
+          count,
+          url,
+          data,
+        } => {
+          if index != received {
+            log::warn!(
+              "ignored render chunk {} while expecting {}",
+              index,
+              received
+            );
+            continue;
+          }
+          content.push_str(&data);
+          received += 1;
+          if received >= count {
+            return Ok(Page { url, content });
+          }
+          guard
+            .window
+            .eval(&format!("window.__readabilitySend{}({})", nonce, received))?;
+        }
+      }
+    }
+  }
+}
+
+/// Closes a render window when the render finishes or is cancelled, such as by
+/// the timeout of `fetch`.
+struct RenderGuard {
+  window: Window,
+}
+
+impl Drop for RenderGuard {
+  fn drop(&mut self) {
+    if let Err(e) = self.window.close() {
+      log::warn!("failed to close {}: {}", self.window.label(), e);
+    }
+  }
+}
+
+/// What a render window passed by navigating to `RENDER_HOST`.
+enum RenderMessage {
+  /// the page has loaded and settled
+  Ready,
+  /// the `index`th of `count` parts of the rendered page
+  Chunk {
+    index: usize,
+    count: usize,
+    url: String,
+    data: String,
+  },
+}
+
+/// Parse `https://RENDER_HOST/{nonce}/ready` or
+/// `https://RENDER_HOST/{nonce}/{index}/{count}?url=...&data=...`.
+fn render_message(url: &Url, nonce: &str) -> Option<RenderMessage> {
+  let mut segments = url.path_segments()?;
+  if segments.next()? != nonce {
+    return None;
+  }
+
+  let first = segments.next()?;
+  if first == "ready" {
+    return Some(RenderMessage::Ready);
+  }
+  let index = first.parse().ok()?;
+  let count = segments.next()?.parse().ok()?;
+
+  let mut page_url = None;
+  let mut data = None;
+  for (key, value) in url.query_pairs() {
+    match &*key {
+      "url" => page_url = Some(value.into_owned()),
+      "data" => data = Some(value.into_owned()),
+      _ => {}
+    }
+  }
+  Some(RenderMessage::Chunk {
+    index,
+    count,
+    url: page_url?,
+    data: data?,
+  })
+}
+
+/// Open the hidden windows hosting `Readability.js`. They join the pool of
+/// `ReadabilityRepositoryImpl` once loaded, so call it after the repository is
+/// created.
+pub fn spawn_workers(app: &AppHandle, count: usize) -> Result<()> {
+  for i in 0..count.max(1) {
+    WindowBuilder::new(
+      app,
+      format!("{}-{}", WORKER_LABEL_PREFIX, i),
+      WindowUrl::App(WORKER_URL.into()),
+    )
+    .title("Readability worker")
+    .visible(false)
+    .build()?;
+  }
+
+  Ok(())
+}
+
+fn page_of(entry: &CacheEntry) -> Page {
+  let tld = Url::parse(&entry.url).ok().and_then(|url| {
+    url
+      .domain()
+      .and_then(|d| d.rsplit('.').next())
+      .map(String::from)
+  });
+
+  Page {
+    url: entry.url.clone(),
+    content: decode_html(&entry.data, entry.content_type.as_deref(), tld.as_deref()),
+  }
+}
+
+fn non_empty(text: Option<String>) -> Option<String> {
+  text
+    .map(|text| text.trim().to_string())
+    .filter(|text| !text.is_empty())
+}
+
+fn next_page_selectors(rule: Option<&SiteRule>) -> &[String] {
+  rule.map_or(&[], |rule| &rule.next_page_link)
+}
+
+/// Classify an error of a conversion stage, keeping timeouts apart.
+fn stage_error(e: anyhow::Error, stage: fn(anyhow::Error) -> ConvertError) -> ConvertError {
+  if e.is::<Elapsed>() {
+    ConvertError::Timeout(e)
+  } else {
+    stage(e)
+  }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+
+use kuchikiki::NodeRef;
+use url::Url;
+
+use crate::library::html::{inner_html, parse};
+
+/// query parameters used to number the pages of an article
+///
+/// `p` is left out, as it is the ID of the post on WordPress.
+const PAGE_PARAMETERS: &[&str] = &["page", "pg"];
+
+const HEADINGS: &str = "h1, h2, h3, h4, h5, h6, header";
+
+/// Find the link to the next page of the article on `url`.
+///
+/// `selectors` of the site rule win over `rel="next"`, which wins over a link
+/// to the same path with the next `?page=` or `/page/` number. As blogs also
+/// mark the following post with `rel="next"`, such links are only followed to
+/// a later page of the same path.
+pub fn next_page(html: &str, url: &Url, selectors: &[String]) -> Option<Url> {
+  let document = parse(html);
+
+  let explicit = selectors
+    .iter()
+    .find_map(|selector| select_href(&document, selector, url));
+  let rel_next = || {
+    ["link[rel~=next]", "a[rel~=next]"]
+      .iter()
+      .find_map(|selector| {
+        select_href(&document, selector, url).filter(|next| is_later_page(next, url))
+      })
+  };
+
+  explicit
+    .or_else(rel_next)
+    .or_else(|| numbered(&document, url))
+    .filter(|next| next.host_str() == url.host_str() && !same_page(next, url))
+}
+
+/// The `href` of the first node matching `selector`, resolved against `url`.
+fn select_href(document: &NodeRef, selector: &str, url: &Url) -> Option<Url> {
+  let node = document.select(selector).ok()?.next()?;
+  let href = node.attributes.borrow().get("href")?.to_string();
+  url.join(&href).ok()
+}
+
+/// A link to the page numbered one more than `url`.
+fn numbered(document: &NodeRef, url: &Url) -> Option<Url> {
+  let (base, page) = page_number(url);
+  let links = document.select("a[href]").ok()?;
+
+  for link in links {
+    let href = link.attributes.borrow().get("href").map(String::from);
+    let Some(next) = href.and_then(|href| url.join(&href).ok()) else {
+      continue;
+    };
+    if next.host_str() != url.host_str() {
+      continue;
+    }
+    let (next_base, next_page) = page_number(&next);
+    if next_base == base && next_page == page + 1 {
+      return Some(next);
+    }
+  }
+
+  None
+}
+
+/// The URL without its page number, and the page number which is 1 if absent.
+fn page_number(url: &Url) -> (String, u32) {
+  let mut url = url.clone();
+  url.set_fragment(None);
+  let mut page = 1;
+
+  let pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+  let kept: Vec<&(String, String)> = pairs
+    .iter()
+    .filter(|(k, v)| match v.parse() {
+      Ok(n) if PAGE_PARAMETERS.contains(&k.as_str()) => {
+        page = n;
+        false
+      }
+      _ => true,
+    })
+    .collect();
+  if kept.is_empty() {
+    url.set_query(None);
+  } else {
+    url.query_pairs_mut().clear().extend_pairs(kept);
+  }
+
+  // e.g. /2024/01/story/page/2/
+  let path = url.path().trim_end_matches('/').to_string();
+  if let Some((head, n)) = path.rsplit_once('/') {
+    if let (Some(head), Ok(n)) = (head.strip_suffix("/page"), n.parse()) {
+      page = n;
+      url.set_path(head);
+    } else {
+      url.set_path(&path);
+    }
+  }
+
+  (url.into(), page)
+}
+
+fn same_page(a: &Url, b: &Url) -> bool {
+  page_number(a) == page_number(b)
+}
+
+/// Whether `next` is a page after `url` of the same article.
+fn is_later_page(next: &Url, url: &Url) -> bool {
+  let (next_base, next_page) = page_number(next);
+  let (base, page) = page_number(url);
+  next_base == base && next_page > page
+}
+
+/// Normalized texts of the headings in `html`.
+pub fn headings(html: &str) -> HashSet<String> {
+  let document = parse(html);
+  let mut ret = HashSet::new();
+  if let Ok(nodes) = document.select(HEADINGS) {
+    for node in nodes {
+      ret.insert(normalize(&node.text_contents()));
+    }
+  }
+  ret.remove("");
+  ret
+}
+
+/// Remove the headings repeated on every page, such as the title and the
+/// byline, from a following page.
+pub fn remove_repeated(html: &str, known: &HashSet<String>) -> String {
+  let document = parse(html);
+  let repeated: Vec<NodeRef> = document
+    .select(HEADINGS)
+    .map(|nodes| {
+      nodes
+        .filter(|node| known.contains(&normalize(&node.text_contents())))
+        .map(|node| node.as_node().clone())
+        .collect()
+    })
+    .unwrap_or_default();
+  for node in repeated {
+    node.detach();
+  }
+  inner_html(&document)
+}
+
+pub fn normalize(text: &str) -> String {
+  text
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+    .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn next(html: &str, url: &str) -> Option<String> {
+    next_page(html, &Url::parse(url).unwrap(), &[]).map(String::from)
+  }
+
+  #[test]
+  fn next_page_follows_rel_next_to_a_later_page() {
+    assert_eq!(
+      next(
+        r#"<link rel="next" href="/story/page/2/">"#,
+        "https://example.com/story/"
+      )
+      .as_deref(),
+      Some("https://example.com/story/page/2/")
+    );
+    assert_eq!(
+      next(
+        r#"<a rel="prev next" href="?page=3">Next</a>"#,
+        "https://example.com/story?page=2"
+      )
+      .as_deref(),
+      Some("https://example.com/story?page=3")
+    );
+  }
+
+  #[test]
+  fn next_page_ignores_rel_next_to_another_article() {
+    assert_eq!(
+      next(
+        r#"<link rel="next" href="/2024/01/other-story/">"#,
+        "https://example.com/2024/01/story/"
+      ),
+      None
+    );
+    // the post ID of WordPress is not a page number
+    assert_eq!(
+      next(
+        r#"<link rel="next" href="/?p=124">"#,
+        "https://example.com/?p=123"
+      ),
+      None
+    );
+  }
+
+  #[test]
+  fn next_page_prefers_the_site_rule() {
+    let url = Url::parse("https://example.com/story").unwrap();
+    let html = r#"<a class="more" href="/story/continued">More</a><a href="?page=2">2</a>"#;
+    assert_eq!(
+      next_page(html, &url, &[".more".into()]).map(String::from),
+      Some("https://example.com/story/continued".into())
+    );
+    assert_eq!(
+      next_page(html, &url, &[]).map(String::from),
+      Some("https://example.com/story?page=2".into())
+    );
+  }
+
+  #[test]
+  fn next_page_stays_on_the_site() {
+    assert_eq!(
+      next(
+        r#"<a rel="next" href="https://other.example/story/page/2">Next</a>"#,
+        "https://example.com/story"
+      ),
+      None
+    );
+  }
+
+  #[test]
+  fn numbered_finds_the_following_number() {
+    let url = Url::parse("https://example.com/story?id=7&page=2#top").unwrap();
+    let document = parse(
+      r#"<a href="?id=7&page=1">1</a><a href="?id=8&page=3">other</a><a href="?id=7&page=3">3</a>"#,
+    );
+    assert_eq!(
+      numbered(&document, &url).map(String::from),
+      Some("https://example.com/story?id=7&page=3".into())
+    );
+
+    let url = Url::parse("https://example.com/story/page/3").unwrap();
+    let document = parse(r#"<a href="/story/page/2">2</a><a href="/story/page/4/">4</a>"#);
+    assert_eq!(
+      numbered(&document, &url).map(String::from),
+      Some("https://example.com/story/page/4/".into())
+    );
+  }
+
+  #[test]
+  fn remove_repeated_drops_known_headings() {
+    let known = headings("<h1>Title</h1><p>a</p>");
+    assert_eq!(
+      remove_repeated("<h1> title </h1><h2>Part 2</h2><p>b</p>", &known),
+      "<h2>Part 2</h2><p>b</p>"
+    );
+  }
+}
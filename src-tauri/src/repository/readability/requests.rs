@@ -0,0 +1,184 @@
+use std::{
+  collections::HashMap,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+  },
+  time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::oneshot, time::timeout};
+
+use super::ReadabilityOutput;
+
+pub type RequestId = u64;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Request {
+  pub id: RequestId,
+  /// relative URLs in `content` are resolved against it
+  pub url: String,
+  pub content: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Response {
+  pub id: RequestId,
+  pub article: Option<ReadabilityOutput>,
+  pub error: Option<String>,
+}
+
+impl Response {
+  /// The extracted article, or the error thrown by `Readability.js`.
+  pub fn article(self) -> Result<ReadabilityOutput> {
+    match (self.article, self.error) {
+      (Some(article), _) => Ok(article),
+      (None, Some(error)) => bail!("readability request {} failed: {}", self.id, error),
+      (None, None) => bail!("readability request {} found no content", self.id),
+    }
+  }
+}
+
+/// Requests waiting for their response, which arrives as an event carrying
+/// the id of the request.
+#[derive(Default)]
+pub struct PendingRequests {
+  next_id: AtomicU64,
+  /// raw response payloads waiting to be picked up
+  senders: Mutex<HashMap<RequestId, oneshot::Sender<Value>>>,
+}
+
+impl PendingRequests {
+  pub fn next_id(&self) -> RequestId {
+    self.next_id.fetch_add(1, Ordering::Relaxed)
+  }
+
+  /// Start a request, returning its id and where its response arrives.
+  pub fn start(&self) -> (RequestId, oneshot::Receiver<Value>) {
+    let id = self.next_id();
+    let (tx, rx) = oneshot::channel();
+    self.senders.lock().unwrap().insert(id, tx);
+    (id, rx)
+  }
+
+  /// Forget a request that won't get a response.
+  pub fn cancel(&self, id: RequestId) {
+    self.senders.lock().unwrap().remove(&id);
+  }
+
+  pub async fn wait<T: for<'de> Deserialize<'de>>(
+    &self,
+    id: RequestId,
+    rx: oneshot::Receiver<Value>,
+    duration: Duration,
+  ) -> Result<T> {
+    match timeout(duration, rx).await {
+      Ok(value) => Ok(serde_json::from_value(value?)?),
+      Err(e) => {
+        self.cancel(id);
+        Err(anyhow::Error::new(e).context(format!("readability request {} timed out", id)))
+      }
+    }
+  }
+
+  /// Pass a response to the request waiting for it.
+  pub fn resolve(&self, payload: Option<&str>) -> Result<()> {
+    let value: Value = serde_json::from_str(payload.context("empty payload")?)?;
+    let id = value
+      .get("id")
+      .and_then(Value::as_u64)
+      .ok_or_else(|| anyhow!("response without id"))?;
+
+    let tx = self.senders.lock().unwrap().remove(&id);
+    match tx {
+      // the receiver is gone if the request has already timed out
+      Some(tx) => {
+        let _ = tx.send(value);
+      }
+      None => log::warn!("no pending request for response {}", id),
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use serde_json::json;
+
+  use super::*;
+
+  const WAIT: Duration = Duration::from_secs(5);
+
+  #[tokio::test]
+  async fn concurrent_requests_get_their_own_response() {
+    let pending = Arc::new(PendingRequests::default());
+    let (first, first_rx) = pending.start();
+    let (second, second_rx) = pending.start();
+    assert_ne!(first, second);
+
+    let wait = |id, rx| {
+      let pending = pending.clone();
+      tokio::spawn(async move { pending.wait::<Value>(id, rx, WAIT).await })
+    };
+    let first_ret = wait(first, first_rx);
+    let second_ret = wait(second, second_rx);
+
+    // answered out of order
+    let response = |id, data| Some(json!({ "id": id, "data": data }).to_string());
+    pending.resolve(response(second, "b").as_deref()).unwrap();
+    pending.resolve(response(first, "a").as_deref()).unwrap();
+
+    assert_eq!(first_ret.await.unwrap().unwrap()["data"], "a");
+    assert_eq!(second_ret.await.unwrap().unwrap()["data"], "b");
+    assert!(pending.senders.lock().unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn late_responses_are_dropped() {
+    let pending = PendingRequests::default();
+    let (id, rx) = pending.start();
+
+    let e = pending
+      .wait::<Value>(id, rx, Duration::from_millis(10))
+      .await
+      .unwrap_err();
+    assert_eq!(
+      e.to_string(),
+      format!("readability request {} timed out", id)
+    );
+    assert!(pending.senders.lock().unwrap().is_empty());
+
+    let late = json!({ "id": id, "error": null }).to_string();
+    pending.resolve(Some(&late)).unwrap();
+    assert!(pending.senders.lock().unwrap().is_empty());
+  }
+
+  #[tokio::test]
+  async fn errors_thrown_by_readability_become_errors() {
+    let pending = PendingRequests::default();
+    let (id, rx) = pending.start();
+
+    let payload = json!({ "id": id, "error": "TypeError: doc is null" }).to_string();
+    pending.resolve(Some(&payload)).unwrap();
+
+    let response: Response = pending.wait(id, rx, WAIT).await.unwrap();
+    let e = response.article().unwrap_err();
+    assert_eq!(
+      e.to_string(),
+      format!("readability request {} failed: TypeError: doc is null", id)
+    );
+  }
+
+  #[test]
+  fn resolve_rejects_responses_without_id() {
+    let pending = PendingRequests::default();
+    assert!(pending.resolve(Some(r#"{"error":"x"}"#)).is_err());
+    assert!(pending.resolve(None).is_err());
+  }
+}
@@ -0,0 +1,276 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use kuchikiki::NodeRef;
+use serde::Deserialize;
+use url::Url;
+
+use super::ReadabilityOutput;
+use crate::library::html::parse;
+
+/// rules shipped with the app, overridden per domain by the user's
+const BUNDLED_RULES: &str = include_str!("sites.json");
+
+/// How to extract the articles of a site, in the spirit of ftr-site-config.
+///
+/// Each list holds CSS selectors tried in order until one matches.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SiteRule {
+  /// applies to this host and its subdomains
+  pub domain: String,
+  /// the article body, used instead of `Readability.js`
+  #[serde(default)]
+  pub body: Vec<String>,
+  #[serde(default)]
+  pub title: Vec<String>,
+  #[serde(default)]
+  pub author: Vec<String>,
+  /// elements removed before extraction
+  #[serde(default)]
+  pub strip: Vec<String>,
+  /// a link to the whole article on one page, followed before extraction
+  #[serde(default)]
+  pub single_page_link: Vec<String>,
+  /// a link to the next page of an article split into pages
+  #[serde(default)]
+  pub next_page_link: Vec<String>,
+  /// use the page body as is when no `body` selector matches
+  #[serde(default)]
+  pub bypass: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SiteRules {
+  rules: Vec<SiteRule>,
+}
+
+impl SiteRules {
+  /// The bundled rules, with the ones in `path` taking precedence.
+  pub fn load(path: &Path) -> Result<Self> {
+    let mut rules: Vec<SiteRule> = serde_json::from_str(BUNDLED_RULES)?;
+
+    if path.exists() {
+      let json = fs::read_to_string(path)?;
+      let overrides: Vec<SiteRule> =
+        serde_json::from_str(&json).with_context(|| format!("invalid {}", path.display()))?;
+      rules.retain(|rule| !overrides.iter().any(|o| o.domain == rule.domain));
+      rules.extend(overrides);
+    }
+
+    Ok(Self { rules })
+  }
+
+  /// The rule of the most specific domain matching `url`.
+  pub fn find(&self, url: &Url) -> Option<&SiteRule> {
+    let host = url.host_str()?;
+    self
+      .rules
+      .iter()
+      .filter(|rule| host == rule.domain || host.ends_with(&format!(".{}", rule.domain)))
+      .max_by_key(|rule| rule.domain.len())
+  }
+}
+
+impl SiteRule {
+  /// Remove the `strip` elements from the page.
+  pub fn strip(&self, html: &str) -> String {
+    if self.strip.is_empty() {
+      return html.into();
+    }
+
+    let document = parse(html);
+    for selector in &self.strip {
+      for node in select_all(&document, selector) {
+        node.detach();
+      }
+    }
+    document.to_string()
+  }
+
+  pub fn single_page_link(&self, html: &str, base: &Url) -> Option<Url> {
+    let document = parse(html);
+    self.single_page_link.iter().find_map(|selector| {
+      let node = select_all(&document, selector).into_iter().next()?;
+      let element = node.as_element()?;
+      let href = element.attributes.borrow().get("href")?.to_string();
+      base.join(&href).ok()
+    })
+  }
+
+  /// Extract the article with the selectors, or `None` to leave it to
+  /// `Readability.js`.
+  pub(super) fn extract(&self, html: &str) -> Option<ReadabilityOutput> {
+    let document = parse(html);
+
+    let body = self
+      .body
+      .iter()
+      .map(|selector| select_all(&document, selector))
+      .find(|nodes| !nodes.is_empty())
+      .or_else(|| {
+        self
+          .bypass
+          .then(|| select_all(&document, "body"))
+          .filter(|nodes| !nodes.is_empty())
+      })?;
+
+    let content: String = body.iter().map(|node| node.to_string()).collect();
+    let text: String = body.iter().map(|node| node.text_contents()).collect();
+    let text = text.trim().to_string();
+
+    Some(ReadabilityOutput {
+      title: self
+        .title(&document)
+        .or_else(|| select_text(&document, "title"))
+        .unwrap_or_default(),
+      content,
+      length: text.chars().count(),
+      excerpt: meta(&document, &["description", "og:description"])
+        .unwrap_or_else(|| text.chars().take(200).collect()),
+      textContent: text,
+      byline: self
+        .author(&document)
+        .or_else(|| meta(&document, &["author"])),
+      dir: attribute(&document, "html", "dir"),
+      siteName: meta(&document, &["og:site_name"]),
+      lang: attribute(&document, "html", "lang"),
+    })
+  }
+
+  /// Override what `Readability.js` found with the `title` and `author`
+  /// selectors.
+  pub(super) fn apply(&self, html: &str, output: &mut ReadabilityOutput) {
+    if self.title.is_empty() && self.author.is_empty() {
+      return;
+    }
+
+    let document = parse(html);
+    if let Some(title) = self.title(&document) {
+      output.title = title;
+    }
+    if let Some(author) = self.author(&document) {
+      output.byline = Some(author);
+    }
+  }
+
+  fn title(&self, document: &NodeRef) -> Option<String> {
+    self.title.iter().find_map(|s| select_text(document, s))
+  }
+
+  fn author(&self, document: &NodeRef) -> Option<String> {
+    self.author.iter().find_map(|s| select_text(document, s))
+  }
+}
+
+fn select_all(document: &NodeRef, selector: &str) -> Vec<NodeRef> {
+  match document.select(selector) {
+    Ok(nodes) => nodes.map(|node| node.as_node().clone()).collect(),
+    Err(_) => {
+      log::warn!("invalid selector in site rules: {}", selector);
+      vec![]
+    }
+  }
+}
+
+fn select_text(document: &NodeRef, selector: &str) -> Option<String> {
+  let text = select_all(document, selector).first()?.text_contents();
+  let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+  (!text.is_empty()).then_some(text)
+}
+
+fn attribute(document: &NodeRef, selector: &str, name: &str) -> Option<String> {
+  let node = select_all(document, selector).into_iter().next()?;
+  let element = node.as_element()?;
+  let value = element.attributes.borrow().get(name)?.to_string();
+  Some(value)
+}
+
+/// The content of the first `<meta>` with one of `names` as name or property.
+fn meta(document: &NodeRef, names: &[&str]) -> Option<String> {
+  names.iter().find_map(|name| {
+    let selector = format!(r#"meta[name="{0}"], meta[property="{0}"]"#, name);
+    attribute(document, &selector, "content").filter(|v| !v.trim().is_empty())
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rule(domain: &str) -> SiteRule {
+    SiteRule {
+      domain: domain.into(),
+      ..Default::default()
+    }
+  }
+
+  fn find<'a>(rules: &'a SiteRules, url: &str) -> Option<&'a str> {
+    let url = Url::parse(url).unwrap();
+    rules.find(&url).map(|rule| rule.domain.as_str())
+  }
+
+  #[test]
+  fn find_prefers_the_most_specific_domain() {
+    let rules = SiteRules {
+      rules: vec![rule("example.com"), rule("blog.example.com")],
+    };
+
+    assert_eq!(find(&rules, "https://example.com/a"), Some("example.com"));
+    assert_eq!(
+      find(&rules, "https://www.example.com/a"),
+      Some("example.com")
+    );
+    assert_eq!(
+      find(&rules, "https://blog.example.com/a"),
+      Some("blog.example.com")
+    );
+    assert_eq!(
+      find(&rules, "https://en.blog.example.com/a"),
+      Some("blog.example.com")
+    );
+    assert_eq!(find(&rules, "https://badexample.com/a"), None);
+    assert_eq!(find(&rules, "https://example.com.evil.net/a"), None);
+  }
+
+  #[test]
+  fn strip_removes_matching_elements() {
+    let site = SiteRule {
+      strip: vec![".ad".into(), "aside, nav".into()],
+      ..rule("example.com")
+    };
+
+    let html = site.strip(
+      r#"<nav>menu</nav><p>a</p><div class="ad">buy</div><aside>related</aside><p class="adx">b</p>"#,
+    );
+    assert!(html.contains("<p>a</p>"));
+    assert!(html.contains(r#"<p class="adx">b</p>"#));
+    for removed in ["menu", "buy", "related"] {
+      assert!(!html.contains(removed), "{}", removed);
+    }
+
+    let html = "<p>a</p>";
+    assert_eq!(rule("example.com").strip(html), html);
+  }
+
+  #[test]
+  fn single_page_link_resolves_the_first_match() {
+    let site = SiteRule {
+      single_page_link: vec!["a.missing".into(), "a.all".into()],
+      ..rule("example.com")
+    };
+    let base = Url::parse("https://example.com/news/1?page=2").unwrap();
+
+    assert_eq!(
+      site
+        .single_page_link(
+          r#"<a class="all" href="1?all=1">all</a><a class="all" href="/x">x</a>"#,
+          &base
+        )
+        .map(String::from)
+        .as_deref(),
+      Some("https://example.com/news/1?all=1")
+    );
+    assert_eq!(site.single_page_link("<a href='/x'>x</a>", &base), None);
+  }
+}
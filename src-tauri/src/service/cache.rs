@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use crate::domain::{
+  repository::cache::CacheRepository,
+  service::cache::{CacheService, ClearCacheOutput},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use shaku::Provider;
+
+#[derive(Provider)]
+#[shaku(interface = CacheService)]
+pub struct CacheServiceImpl {
+  #[shaku(inject)]
+  repository: Arc<dyn CacheRepository>,
+}
+
+#[async_trait]
+impl CacheService for CacheServiceImpl {
+  async fn clear(&mut self) -> Result<ClearCacheOutput> {
+    let ret = self.repository.clear().await?;
+    Ok(ClearCacheOutput {
+      entries: ret.entries,
+      size: ret.size,
+    })
+  }
+}
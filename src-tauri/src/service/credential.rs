@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use crate::domain::{
+  repository::credential::{self, CredentialRepository},
+  service::credential::{
+    CredentialService, CredentialSummary, ImportCookiesInput, ImportCookiesOutput,
+    ListCredentialsOutput, RemoveCredentialInput, SetHeadersInput,
+  },
+};
+use anyhow::Result;
+use shaku::Provider;
+
+#[derive(Provider)]
+#[shaku(interface = CredentialService)]
+pub struct CredentialServiceImpl {
+  #[shaku(inject)]
+  repository: Arc<dyn CredentialRepository>,
+}
+
+impl CredentialService for CredentialServiceImpl {
+  fn list(&mut self) -> Result<ListCredentialsOutput> {
+    let credentials = self
+      .repository
+      .list()
+      .into_iter()
+      .map(|c| CredentialSummary {
+        domain: c.domain,
+        header_names: c.headers.into_keys().collect(),
+        cookies: c.cookies.len(),
+      })
+      .collect();
+
+    Ok(ListCredentialsOutput { credentials })
+  }
+
+  fn import_cookies(&mut self, input: ImportCookiesInput) -> Result<ImportCookiesOutput> {
+    let ret = self
+      .repository
+      .import_cookies(credential::ImportCookiesInput {
+        contents: input.contents,
+      })?;
+
+    Ok(ImportCookiesOutput {
+      domains: ret.domains,
+      cookies: ret.cookies,
+    })
+  }
+
+  fn set_headers(&mut self, input: SetHeadersInput) -> Result<()> {
+    self.repository.set_headers(credential::SetHeadersInput {
+      domain: input.domain,
+      headers: input.headers,
+    })
+  }
+
+  fn remove(&mut self, input: RemoveCredentialInput) -> Result<()> {
+    self.repository.remove(credential::RemoveInput {
+      domain: input.domain,
+    })
+  }
+}
@@ -1,2 +1,4 @@
+pub mod cache;
+pub mod credential;
 pub mod pocket;
 pub mod send_to_kindle;
@@ -1,14 +1,26 @@
 use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use crate::domain::{
+  repository::image::{FetchInput, ImageRepository},
   repository::kindle::{self, KindleRepository},
   repository::pocket::{MarkAsSentInput, PocketRepository},
-  repository::readability::{ConvertInput, ReadabilityRepository},
-  service::send_to_kindle::{SendInput, SendOutput, SendToKindleService},
+  repository::readability::{ConvertError, ConvertInput, ReadabilityRepository},
+  service::send_to_kindle::{
+    ArticleResult, ArticleStatus, FailureReason, SendError, SendInput, SendOutput,
+    SendToKindleService, VolumeResult, VolumeStatus,
+  },
+  Article, ArticleId, Image, ImageStats,
 };
-use anyhow::Result;
+use crate::library::{
+  html::{escape, image_sources, rewrite_images},
+  sanitize::sanitize,
+};
+use anyhow::{anyhow, Result};
+use futures::future::join_all;
 use shaku::Provider;
+use url::Url;
 
 #[derive(Provider)]
 #[shaku(interface = SendToKindleService)]
@@ -19,36 +31,487 @@ pub struct SendToKindleServiceImpl {
   pocket_repository: Arc<dyn PocketRepository>,
   #[shaku(inject)]
   readability_repository: Arc<dyn ReadabilityRepository>,
+  #[shaku(inject)]
+  image_repository: Arc<dyn ImageRepository>,
 }
 
 #[async_trait]
 impl SendToKindleService for SendToKindleServiceImpl {
-  async fn send(&mut self, input: SendInput) -> Result<SendOutput> {
-    let ids: Vec<_> = input.articles.iter().map(|a| a.id.clone()).collect();
-    self
-      .pocket_repository
-      .mark_as_sent(MarkAsSentInput { ids })
-      .await?;
-
+  async fn send(&mut self, input: SendInput) -> Result<SendOutput, SendError> {
     log::info!("{:?}", &input.articles);
 
+    // the repository bounds how many articles are converted at once.
+    // `join_all` keeps the order of the input
+    let readability_repository = &self.readability_repository;
+    let converted = join_all(
+      input
+        .articles
+        .iter()
+        .cloned()
+        .map(|article| readability_repository.convert(ConvertInput { article })),
+    )
+    .await;
+
     let mut articles_with_content = vec![];
-    for article in input.articles.into_iter() {
-      let ret = self
-        .readability_repository
-        .convert(ConvertInput { article })
-        .await?;
-      articles_with_content.push(ret.article);
+    let mut results = vec![];
+    for (article, ret) in input.articles.into_iter().zip(converted) {
+      let status = match ret {
+        Ok(ret) => {
+          articles_with_content.push(ret.article);
+          ArticleStatus::Sent
+        }
+        Err(e) => {
+          log::warn!("failed to convert {}: {}", &article.url, &e);
+          let reason = match &e {
+            ConvertError::Fetch(_) => FailureReason::Fetch,
+            ConvertError::Extract(_) => FailureReason::Extract,
+            ConvertError::Timeout(_) => FailureReason::Timeout,
+          };
+          let message = e.to_string();
+          if input.placeholder_for_failures {
+            articles_with_content.push(placeholder(&article));
+            ArticleStatus::Placeholder { reason, message }
+          } else {
+            ArticleStatus::Failed { reason, message }
+          }
+        }
+      };
+      results.push(ArticleResult {
+        id: article.id,
+        title: article.title,
+        url: article.url,
+        status,
+      });
+    }
+
+    let ids: Vec<_> = results
+      .iter()
+      .filter(|r| matches!(r.status, ArticleStatus::Sent))
+      .map(|r| r.id.clone())
+      .collect();
+
+    if ids.is_empty() {
+      log::warn!("no article could be converted");
+      return Ok(SendOutput {
+        articles: results,
+        image_stats: ImageStats::default(),
+        volumes: vec![],
+        warning: None,
+      });
+    }
+
+    let (images, covers) = self.embed_images(&mut articles_with_content).await;
+
+    // chapters of the book must be well-formed XHTML
+    for article in articles_with_content.iter_mut() {
+      article.contents = sanitize(&article.contents);
     }
 
-    self
+    let ret = self
       .kindle_repository
       .send(kindle::SendInput {
         articles: articles_with_content,
+        images,
+        covers,
+        mode: input.mode,
+      })
+      .await?;
+
+    // nothing was delivered
+    if ret.volumes.iter().all(|v| v.result.is_err()) {
+      if let Some(e) = ret.volumes.into_iter().find_map(|v| v.result.err()) {
+        return Err(e.into());
+      }
+      return Err(anyhow!("no volume was built").into());
+    }
+
+    // the articles in undelivered volumes are reported as failed
+    let mut undelivered = HashMap::new();
+    let mut volumes = vec![];
+    for volume in ret.volumes {
+      let status = match volume.result {
+        Ok(_) => VolumeStatus::Sent,
+        Err(e) => {
+          for id in &volume.ids {
+            undelivered.insert(id.clone(), e.to_string());
+          }
+          VolumeStatus::Failed {
+            reason: (&e).into(),
+            message: e.to_string(),
+          }
+        }
+      };
+      volumes.push(VolumeResult {
+        title: volume.title,
+        articles: volume.ids,
+        size: volume.size,
+        status,
+      });
+    }
+    for result in results.iter_mut() {
+      if let Some(message) = undelivered.remove(&result.id) {
+        result.status = ArticleStatus::Failed {
+          reason: FailureReason::Delivery,
+          message,
+        };
+      }
+    }
+
+    let ids: Vec<_> = results
+      .iter()
+      .filter(|r| matches!(r.status, ArticleStatus::Sent))
+      .map(|r| r.id.clone())
+      .collect();
+    // the books are already delivered, so failing here would hide the
+    // statuses and make a retry send the articles twice
+    let warning = match self
+      .pocket_repository
+      .mark_as_sent(MarkAsSentInput { ids })
+      .await
+    {
+      Ok(_) => None,
+      Err(e) => {
+        log::warn!("failed to mark the articles as sent: {:#}", &e);
+        Some(format!(
+          "failed to mark the articles as sent in Pocket: {:#}",
+          e
+        ))
+      }
+    };
+
+    Ok(SendOutput {
+      articles: results,
+      image_stats: ret.image_stats,
+      volumes,
+      warning,
+    })
+  }
+}
+
+impl SendToKindleServiceImpl {
+  /// Download the images of the articles and point them at the copies in the book.
+  /// The Pocket cover of each article is downloaded for the cover of the book
+  /// too, and returned by the ID of the article.
+  async fn embed_images(
+    &self,
+    articles: &mut [Article],
+  ) -> (Vec<Image>, HashMap<ArticleId, String>) {
+    // use Pocket's cover as a lead image when the body has none
+    for article in articles.iter_mut() {
+      if let Some(cover) = &article.cover {
+        if image_sources(&article.contents).is_empty() {
+          article.contents = format!(
+            r#"<figure><img src="{}" alt="" /></figure>{}"#,
+            escape(cover),
+            &article.contents
+          );
+        }
+      }
+    }
+
+    let mut urls = vec![];
+    let mut seen = HashSet::new();
+    for article in articles.iter() {
+      let sources = image_sources(&article.contents)
+        .into_iter()
+        .chain(article.cover.clone());
+      for src in sources {
+        if let Some(url) = resolve_url(&article.url, &src) {
+          if seen.insert(url.clone()) {
+            urls.push(url);
+          }
+        }
+      }
+    }
+
+    let image_repository = &self.image_repository;
+    let fetched = join_all(
+      urls
+        .iter()
+        .map(|url| image_repository.fetch(FetchInput { url: url.clone() })),
+    )
+    .await;
+
+    let mut images = vec![];
+    let mut paths = HashMap::new();
+    for (url, ret) in urls.into_iter().zip(fetched) {
+      match ret {
+        Ok(ret) => {
+          let path = format!("images/{}.{}", images.len(), extension(&ret.mime));
+          paths.insert(url, path.clone());
+          images.push(Image {
+            path,
+            mime: ret.mime,
+            data: ret.data,
+          });
+        }
+        Err(e) => log::warn!("failed to fetch image {}: {}", &url, e),
+      }
+    }
+
+    // images which could not be downloaded are dropped
+    for article in articles.iter_mut() {
+      article.contents = rewrite_images(&article.contents, |src| {
+        resolve_url(&article.url, src).and_then(|url| paths.get(&url).cloned())
+      });
+    }
+
+    let covers = articles
+      .iter()
+      .filter_map(|article| {
+        let url = resolve_url(&article.url, article.cover.as_deref()?)?;
+        Some((article.id.clone(), paths.get(&url)?.clone()))
+      })
+      .collect();
+
+    log::info!("embedded {} images", images.len());
+
+    (images, covers)
+  }
+}
+
+fn resolve_url(base: &str, url: &str) -> Option<String> {
+  Url::parse(base)
+    .and_then(|base| base.join(url))
+    .map(String::from)
+    .ok()
+}
+
+fn extension(mime: &str) -> &str {
+  match mime {
+    "image/jpeg" => "jpg",
+    "image/svg+xml" => "svg",
+    _ => mime.strip_prefix("image/").unwrap_or("bin"),
+  }
+}
+
+/// A chapter standing in for an article that could not be converted.
+fn placeholder(article: &Article) -> Article {
+  let url = escape(&article.url);
+  Article {
+    contents: format!(
+      r#"<p>This article could not be converted.</p>
+<blockquote><p>{}</p></blockquote>
+<p><a href="{}">{}</a></p>"#,
+      escape(&article.contents),
+      url,
+      url
+    ),
+    ..article.clone()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Mutex;
+
+  use shaku::{module, HasProvider};
+
+  use super::*;
+  use crate::domain::{
+    repository::image::FetchOutput,
+    repository::kindle::{DeliveryError, VolumeOutput},
+    repository::pocket::{ListOutput, StartLoginOutput, WaitLoginOutput},
+    repository::readability::ConvertOutput,
+    DeliveryMode,
+  };
+  use crate::repository::{
+    image::ImageRepositoryImpl, kindle::KindleRepositoryImpl, pocket::PocketRepositoryImpl,
+    readability::ReadabilityRepositoryImpl,
+  };
+
+  module! {
+    TestModule {
+      components = [
+        PocketRepositoryImpl,
+        ReadabilityRepositoryImpl,
+        ImageRepositoryImpl,
+        KindleRepositoryImpl,
+      ],
+      providers = [SendToKindleServiceImpl]
+    }
+  }
+
+  const BROKEN: &str = "https://example.com/broken";
+
+  /// Converts every article but the one at `BROKEN`.
+  struct MockReadability;
+
+  #[async_trait]
+  impl ReadabilityRepository for MockReadability {
+    async fn convert(&self, input: ConvertInput) -> Result<ConvertOutput, ConvertError> {
+      if input.article.url == BROKEN {
+        return Err(ConvertError::Fetch(anyhow!("connection refused")));
+      }
+      Ok(ConvertOutput {
+        article: Article {
+          contents: format!("<p>{} body</p>", input.article.title),
+          ..input.article
+        },
+      })
+    }
+  }
+
+  struct MockImage;
+
+  #[async_trait]
+  impl ImageRepository for MockImage {
+    async fn fetch(&self, input: FetchInput) -> Result<FetchOutput> {
+      Err(anyhow!("{} not found", input.url))
+    }
+  }
+
+  /// Delivers everything in a single volume, keeping the articles it got.
+  #[derive(Default)]
+  struct MockKindle {
+    articles: Arc<Mutex<Vec<Article>>>,
+  }
+
+  #[async_trait]
+  impl KindleRepository for MockKindle {
+    async fn send(&self, input: kindle::SendInput) -> Result<kindle::SendOutput, DeliveryError> {
+      let ids = input.articles.iter().map(|a| a.id.clone()).collect();
+      *self.articles.lock().unwrap() = input.articles;
+      Ok(kindle::SendOutput {
+        image_stats: ImageStats::default(),
+        volumes: vec![VolumeOutput {
+          title: "Reading list".into(),
+          ids,
+          size: 1,
+          result: Ok(()),
+        }],
+      })
+    }
+  }
+
+  /// Keeps the articles marked as sent.
+  #[derive(Default)]
+  struct MockPocket {
+    sent: Arc<Mutex<Vec<ArticleId>>>,
+  }
+
+  #[async_trait]
+  impl PocketRepository for MockPocket {
+    fn is_login(&self) -> bool {
+      true
+    }
+    async fn load_state(&self) -> Result<()> {
+      Ok(())
+    }
+    async fn save_state(&self) -> Result<()> {
+      Ok(())
+    }
+    async fn start_login(&self) -> Result<StartLoginOutput> {
+      unimplemented!()
+    }
+    async fn wait_login(&self) -> Result<WaitLoginOutput> {
+      unimplemented!()
+    }
+    async fn logout(&self) -> Result<()> {
+      Ok(())
+    }
+    async fn list(&self) -> Result<ListOutput> {
+      unimplemented!()
+    }
+    async fn mark_as_sent(&self, input: MarkAsSentInput) -> Result<()> {
+      self.sent.lock().unwrap().extend(input.ids);
+      Ok(())
+    }
+  }
+
+  fn article(id: &str, url: &str) -> Article {
+    Article {
+      id: id.into(),
+      title: format!("Article {}", id),
+      url: url.into(),
+      cover: None,
+      // the Pocket excerpt until converted
+      contents: format!("Excerpt of {} & more", id),
+      byline: None,
+      site_name: None,
+      lang: None,
+      dir: None,
+      excerpt: None,
+      length: None,
+      tags: vec![],
+    }
+  }
+
+  /// Send three articles, the second of which fails to convert, and return
+  /// the output with the articles delivered and marked as sent.
+  async fn send_with_a_failure(
+    placeholder_for_failures: bool,
+  ) -> (SendOutput, Vec<Article>, Vec<ArticleId>) {
+    let kindle = MockKindle::default();
+    let delivered = kindle.articles.clone();
+    let pocket = MockPocket::default();
+    let sent = pocket.sent.clone();
+    let module = TestModule::builder()
+      .with_component_override::<dyn PocketRepository>(Box::new(pocket))
+      .with_component_override::<dyn ReadabilityRepository>(Box::new(MockReadability))
+      .with_component_override::<dyn ImageRepository>(Box::new(MockImage))
+      .with_component_override::<dyn KindleRepository>(Box::new(kindle))
+      .build();
+    let mut service: Box<dyn SendToKindleService> = module.provide().unwrap();
+
+    let ret = service
+      .send(SendInput {
+        articles: vec![
+          article("1", "https://example.com/1"),
+          article("2", BROKEN),
+          article("3", "https://example.com/3"),
+        ],
+        placeholder_for_failures,
+        mode: DeliveryMode::default(),
       })
       .await
       .unwrap();
 
-    Ok(SendOutput {})
+    let delivered = delivered.lock().unwrap().clone();
+    let sent = sent.lock().unwrap().clone();
+    (ret, delivered, sent)
+  }
+
+  #[tokio::test]
+  async fn send_replaces_failed_articles_with_placeholders() {
+    let (ret, delivered, sent) = send_with_a_failure(true).await;
+
+    assert!(matches!(ret.articles[0].status, ArticleStatus::Sent));
+    assert!(matches!(
+      &ret.articles[1].status,
+      ArticleStatus::Placeholder {
+        reason: FailureReason::Fetch,
+        message,
+      } if message.contains("connection refused")
+    ));
+    assert!(matches!(ret.articles[2].status, ArticleStatus::Sent));
+    assert!(matches!(ret.volumes[0].status, VolumeStatus::Sent));
+
+    let ids: Vec<_> = delivered.iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(ids, ["1", "2", "3"]);
+    assert_eq!(delivered[0].contents, "<p>Article 1 body</p>");
+    assert_eq!(delivered[2].contents, "<p>Article 3 body</p>");
+    let placeholder = &delivered[1].contents;
+    assert!(placeholder.contains("<blockquote><p>Excerpt of 2 &amp; more</p></blockquote>"));
+    assert!(placeholder.contains(r#"<a href="https://example.com/broken">"#));
+
+    // the placeholder is sent, but the article is left to be retried
+    assert_eq!(sent, ["1", "3"]);
+  }
+
+  #[tokio::test]
+  async fn send_leaves_out_failed_articles() {
+    let (ret, delivered, sent) = send_with_a_failure(false).await;
+
+    assert!(matches!(
+      ret.articles[1].status,
+      ArticleStatus::Failed {
+        reason: FailureReason::Fetch,
+        ..
+      }
+    ));
+    let ids: Vec<_> = delivered.iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(ids, ["1", "3"]);
+    assert_eq!(sent, ["1", "3"]);
   }
 }
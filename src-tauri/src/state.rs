@@ -1,24 +1,52 @@
-use std::fs::create_dir_all;
+use std::{fs::create_dir_all, sync::Arc, time::Duration};
 
 use anyhow::{Context, Result};
 use shaku::module;
 use tauri::{App, Manager};
 
 use crate::{
-  domain::repository::{pocket::PocketRepository, readability::ReadabilityRepository},
+  domain::repository::{
+    cache::CacheRepository, credential::CredentialRepository, image::ImageRepository,
+    pocket::PocketRepository, readability::ReadabilityRepository,
+  },
   env::Env,
   repository::{
-    kindle::{KindleRepositoryConfig, KindleRepositoryImpl, KindleRepositoryImplParameters},
+    cache::{CacheRepositoryConfig, CacheRepositoryImpl},
+    credential::{CredentialRepositoryConfig, CredentialRepositoryImpl},
+    fetcher::{FetcherConfig, FetcherImpl},
+    image::{ImageRepositoryConfig, ImageRepositoryImpl},
+    kindle::{
+      BookOptions, CoverOptions, ImageOptions, KindleRepositoryConfig, KindleRepositoryImpl,
+      KindleRepositoryImplParameters, SmtpConfig, StyleOptions,
+    },
     pocket::{PocketRepositoryConfig, PocketRepositoryImpl},
-    readability::ReadabilityRepositoryImpl,
+    readability::{
+      spawn_workers, ReadabilityRepositoryConfig, ReadabilityRepositoryImpl, SiteRules,
+    },
+  },
+  service::{
+    cache::CacheServiceImpl, credential::CredentialServiceImpl, pocket::PocketServiceImpl,
+    send_to_kindle::SendToKindleServiceImpl,
   },
-  service::{pocket::PocketServiceImpl, send_to_kindle::SendToKindleServiceImpl},
 };
 
 module! {
     pub MyModule {
-        components = [PocketRepositoryImpl, ReadabilityRepositoryImpl, KindleRepositoryImpl],
-        providers = [PocketServiceImpl, SendToKindleServiceImpl]
+        components = [
+            CacheRepositoryImpl,
+            CredentialRepositoryImpl,
+            FetcherImpl,
+            PocketRepositoryImpl,
+            ReadabilityRepositoryImpl,
+            ImageRepositoryImpl,
+            KindleRepositoryImpl,
+        ],
+        providers = [
+            CacheServiceImpl,
+            CredentialServiceImpl,
+            PocketServiceImpl,
+            SendToKindleServiceImpl,
+        ]
     }
 }
 
@@ -37,26 +65,173 @@ impl AppState {
 
     let state_path = app_dir.join(".pocket-repository-state");
 
+    let image = ImageOptions::default();
+    let image = ImageOptions {
+      max_width: env.image_max_width.unwrap_or(image.max_width),
+      max_height: env.image_max_height.unwrap_or(image.max_height),
+      grayscale: env.image_grayscale.unwrap_or(image.grayscale),
+      dither: env.image_dither.unwrap_or(image.dither),
+      jpeg_quality: env.image_jpeg_quality.unwrap_or(image.jpeg_quality),
+    };
+
+    let images = ImageRepositoryConfig::default();
+    let images = ImageRepositoryConfig {
+      timeout: env
+        .image_fetch_timeout_secs
+        .map_or(images.timeout, Duration::from_secs),
+      max_size: env
+        .image_max_size_mb
+        .map_or(images.max_size, |mb| mb * 1024 * 1024),
+      concurrency: env.image_fetch_concurrency.unwrap_or(images.concurrency),
+    };
+
+    let kindle = KindleRepositoryConfig::default();
+
+    let book = BookOptions::default();
+    let book = BookOptions {
+      title: env.book_title.unwrap_or(book.title),
+      tag_title: env.book_tag_title.unwrap_or(book.tag_title),
+      author: env.book_author.unwrap_or(book.author),
+      publisher: env.book_publisher.unwrap_or(book.publisher),
+      sections: env.book_sections.unwrap_or(book.sections),
+    };
+
+    let style = StyleOptions::load(&app_dir)?;
+    let style = StyleOptions {
+      font_size: env.book_font_size.unwrap_or(style.font_size),
+      line_height: env.book_line_height.unwrap_or(style.line_height),
+      subset_fonts: env.book_subset_fonts.unwrap_or(style.subset_fonts),
+      vertical_japanese: env
+        .book_vertical_japanese
+        .unwrap_or(style.vertical_japanese),
+      ..style
+    };
+
+    let cover = CoverOptions::default();
+    let cover = CoverOptions {
+      enabled: env.cover_enabled.unwrap_or(cover.enabled),
+      lead_image: env.cover_lead_image.unwrap_or(cover.lead_image),
+      ..cover
+    };
+
+    let smtp = SmtpConfig::default();
+    let smtp = SmtpConfig {
+      host: env.smtp_host.unwrap_or(smtp.host),
+      port: env.smtp_port.or(smtp.port),
+      tls: env.smtp_tls.unwrap_or(smtp.tls),
+      auth: env.smtp_auth.unwrap_or(smtp.auth),
+      username: env.smtp_username.or(smtp.username),
+      timeout: env
+        .smtp_timeout_secs
+        .map_or(smtp.timeout, Duration::from_secs),
+      retries: env.smtp_retries.unwrap_or(smtp.retries),
+      ..smtp
+    };
+
+    let http = FetcherConfig::default();
+    let http = FetcherConfig {
+      user_agent: env.http_user_agent.unwrap_or(http.user_agent),
+      connect_timeout: env
+        .http_connect_timeout_secs
+        .map_or(http.connect_timeout, Duration::from_secs),
+      timeout: env
+        .http_timeout_secs
+        .map_or(http.timeout, Duration::from_secs),
+      proxy: env.http_proxy.or(http.proxy),
+      pool_max_idle_per_host: env
+        .http_pool_max_idle_per_host
+        .unwrap_or(http.pool_max_idle_per_host),
+      max_redirects: env.http_max_redirects.unwrap_or(http.max_redirects),
+      ..http
+    };
+
+    let pocket = PocketRepositoryConfig {
+      consumer_key: env.pocket_platform_consumer_key,
+      state_file_path: state_path.to_str().context("parse path fails")?.into(),
+    };
+    let readability = ReadabilityRepositoryConfig::default();
+    let readability = ReadabilityRepositoryConfig {
+      fetch_timeout: env
+        .fetch_timeout_secs
+        .map_or(readability.fetch_timeout, Duration::from_secs),
+      extract_timeout: env
+        .readability_timeout_secs
+        .map_or(readability.extract_timeout, Duration::from_secs),
+      concurrency: env.fetch_concurrency.unwrap_or(readability.concurrency),
+      concurrency_per_domain: env
+        .fetch_concurrency_per_domain
+        .unwrap_or(readability.concurrency_per_domain),
+      render_javascript: env
+        .readability_render_javascript
+        .unwrap_or(readability.render_javascript),
+      rules: SiteRules::load(&app_dir.join("rules.json"))?,
+      max_pages: env.readability_max_pages.unwrap_or(readability.max_pages),
+      workers: env.readability_workers.unwrap_or(readability.workers),
+      ..readability
+    };
+    let workers = readability.workers;
+
+    let cache = CacheRepositoryConfig::default();
+    let cache = CacheRepositoryConfig {
+      dir: app_dir.join("cache"),
+      max_size: env
+        .cache_max_size_mb
+        .map_or(cache.max_size, |mb| mb * 1024 * 1024),
+      fresh_for: env
+        .cache_fresh_for_secs
+        .map_or(cache.fresh_for, Duration::from_secs),
+    };
+
+    let handle = app.app_handle();
+    let pocket_handle = app.app_handle();
+
     let module = MyModule::builder()
-      .with_component_override::<dyn PocketRepository>(Box::new(PocketRepositoryImpl::new(
-        app.app_handle(),
-        PocketRepositoryConfig {
-          consumer_key: env.pocket_platform_consumer_key,
-          state_file_path: state_path.to_str().context("parse path fails")?.into(),
+      .with_component_override::<dyn CacheRepository>(Box::new(CacheRepositoryImpl::new(cache)?))
+      .with_component_override::<dyn CredentialRepository>(Box::new(CredentialRepositoryImpl::new(
+        CredentialRepositoryConfig {
+          file_path: app_dir.join(".credentials.json"),
         },
-      )))
-      .with_component_override::<dyn ReadabilityRepository>(Box::new(
-        ReadabilityRepositoryImpl::new(app.app_handle())?,
-      ))
+      )?))
+      .with_component_parameters::<FetcherImpl>(FetcherImpl::parameters(http)?)
+      .with_component_override_fn::<dyn PocketRepository>(Box::new(move |context| {
+        let fetcher = context.build_component::<FetcherImpl>();
+        Box::new(PocketRepositoryImpl::new(pocket_handle, fetcher, pocket))
+      }))
+      .with_component_override_fn::<dyn ReadabilityRepository>(Box::new(move |context| {
+        let fetcher = context.build_component::<FetcherImpl>();
+        let cache = context.build_component::<CacheRepositoryImpl>();
+        Box::new(ReadabilityRepositoryImpl::new(
+          handle,
+          fetcher,
+          cache,
+          readability,
+        ))
+      }))
+      .with_component_override_fn::<dyn ImageRepository>(Box::new(move |context| {
+        let fetcher = context.build_component::<FetcherImpl>();
+        let cache = context.build_component::<CacheRepositoryImpl>();
+        Box::new(ImageRepositoryImpl::new(fetcher, cache, images))
+      }))
       .with_component_parameters::<KindleRepositoryImpl>(KindleRepositoryImplParameters {
-        config: KindleRepositoryConfig {
+        config: Arc::new(KindleRepositoryConfig {
           send_to: env.send_to_kindle_email,
           send_from: env.email_user,
           password: env.email_password,
-        },
+          image,
+          smtp,
+          book,
+          cover,
+          style,
+          max_message_size: env
+            .kindle_max_message_size_mb
+            .map_or(kindle.max_message_size, |mb| mb * 1000 * 1000),
+          max_attachments: env.kindle_max_attachments.unwrap_or(kindle.max_attachments),
+        }),
       })
       .build();
 
+    spawn_workers(&app.app_handle(), workers)?;
+
     Ok(Self { module })
   }
 }
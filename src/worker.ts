@@ -0,0 +1,41 @@
+// Hidden worker window hosting `Readability.js` for the Rust backend.
+import { Readability } from '@mozilla/readability'
+import { emit, listen } from '@tauri-apps/api/event'
+import { appWindow } from '@tauri-apps/api/window'
+
+type Request = {
+  id: number
+  url: string
+  content: string
+}
+
+const main = async () => {
+  // called from Rust. events are emitted to this window only
+  await listen<Request>('readability-request', (event) => {
+    const { id, url, content } = event.payload
+
+    try {
+      const parser = new DOMParser()
+      const dom = parser.parseFromString(content.trim(), 'text/html')
+
+      // let Readability resolve relative URLs against the article, not this window
+      if (!dom.querySelector('base[href]')) {
+        const base = dom.createElement('base')
+        base.href = url
+        dom.head.prepend(base)
+      }
+
+      const article = new Readability(dom, { debug: false }).parse()
+      console.log('readable', id, article)
+
+      emit('readability-response', { id, article })
+    } catch (e) {
+      console.error('readability', id, e)
+      emit('readability-response', { id, article: null, error: String(e) })
+    }
+  })
+
+  await emit('readability-worker-ready', { label: appWindow.label })
+}
+
+main()